@@ -9,6 +9,7 @@ interface DriveFile {
   mime_type: string;
   parents: string[];
   parent_folder_name: string;
+  folder_breadcrumb?: string;
   keywords?: string[];
   romaji_keywords?: string[];
 }
@@ -117,7 +118,7 @@ export function fuzzySearchFiles(query: string): Array<{
   // 上位20件を返す
   return results.slice(0, 20).map((file) => ({
     title: file.name,
-    subtitle: file.parent_folder_name,
+    subtitle: file.folder_breadcrumb || file.parent_folder_name,
     arg: file.web_view_link,
     uid: file.id,
     valid: true,
@@ -0,0 +1,142 @@
+use crate::infra::DriveApiError;
+use crate::services::search::query::QueryParseError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// 利用側（CLI・Raycast など）が失敗の種類で処理を分けられるようにしたエラー
+// 内部では anyhow を使い、SearchService の公開メソッドの境界でこの型に分類する
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    // 認証情報や検索対象フォルダなど、必要な設定が無い
+    #[error("{0}")]
+    ConfigMissing(String),
+    // 設定ファイルを解釈できない
+    #[error("{0}")]
+    ConfigInvalid(String),
+    // 認証トークンが無い、ブラウザでの認証が完了しなかった
+    #[error("{0}")]
+    AuthRequired(String),
+    // トークンが失効していて更新できない
+    #[error("{0}")]
+    AuthExpired(String),
+    // Drive API がエラーを返した（再試行しても成功しなかったものを含む）
+    #[error("{error:#}")]
+    DriveApi {
+        status: u16,
+        reason: Option<String>,
+        error: anyhow::Error,
+    },
+    // Google に接続できない（タイムアウトを含む）
+    #[error("{0:#}")]
+    Network(anyhow::Error),
+    // 保存データ（ファイル一覧・履歴など）を読み込めない
+    #[error("{0}")]
+    StorageCorrupted(String),
+    #[error("{0:#}")]
+    Io(anyhow::Error),
+    #[error(transparent)]
+    InvalidQuery(#[from] QueryParseError),
+    #[error("別の同期処理が実行中です")]
+    SyncInProgress,
+    #[error("{0:#}")]
+    Other(anyhow::Error),
+}
+
+impl Error {
+    // 種類を表す変わらない文字列（JSON のエラーオブジェクトの code）
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::ConfigMissing(_) => "config_missing",
+            Error::ConfigInvalid(_) => "config_invalid",
+            Error::AuthRequired(_) => "auth_required",
+            Error::AuthExpired(_) => "auth_expired",
+            Error::DriveApi { .. } => "drive_api",
+            Error::Network(_) => "network",
+            Error::StorageCorrupted(_) => "storage_corrupted",
+            Error::Io(_) => "io",
+            Error::InvalidQuery(_) => "invalid_query",
+            Error::SyncInProgress => "sync_in_progress",
+            Error::Other(_) => "other",
+        }
+    }
+}
+
+// 発生箇所で付けた種類（Error）があればそれを使い、なければ原因の連鎖から分類する
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+
+        if let Some(parse_error) = error.downcast_ref::<QueryParseError>() {
+            return Error::InvalidQuery(parse_error.clone());
+        }
+        if let Some(api_error) = error.downcast_ref::<DriveApiError>() {
+            return Error::DriveApi {
+                status: api_error.status.as_u16(),
+                reason: api_error.reason.clone(),
+                error,
+            };
+        }
+
+        for cause in error.chain() {
+            if let Some(reqwest_error) = cause.downcast_ref::<reqwest::Error>() {
+                if reqwest_error.is_connect() || reqwest_error.is_timeout() || reqwest_error.is_request() {
+                    return Error::Network(error);
+                }
+            }
+            #[cfg(feature = "sqlite")]
+            if let Some(sqlite_error) = cause.downcast_ref::<rusqlite::Error>() {
+                if is_sqlite_corruption(sqlite_error) {
+                    return Error::StorageCorrupted(format!("{:#}", error));
+                }
+            }
+            if cause.is::<std::io::Error>() {
+                return Error::Io(error);
+            }
+        }
+
+        Error::Other(error)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn is_sqlite_corruption(error: &rusqlite::Error) -> bool {
+    match error {
+        rusqlite::Error::SqliteFailure(failure, _) => matches!(
+            failure.code,
+            rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase
+        ),
+        rusqlite::Error::FromSqlConversionFailure(..) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn keeps_tagged_errors_and_classifies_causes() {
+        let tagged = anyhow::Error::from(Error::AuthExpired("失効しました".to_string())).context("同期に失敗しました");
+        assert_eq!(Error::from(tagged).code(), "auth_expired");
+
+        let api_error = DriveApiError::from_response(StatusCode::NOT_FOUND, r#"{"error":{"message":"File not found","errors":[{"reason":"notFound"}]}}"#);
+        let error = Error::from(anyhow::Error::from(api_error).context("5回試行しましたが失敗しました"));
+        match error {
+            Error::DriveApi { status, ref reason, .. } => {
+                assert_eq!(status, 404);
+                assert_eq!(reason.as_deref(), Some("notFound"));
+            }
+            ref other => panic!("DriveApi になっていません: {:?}", other),
+        }
+        assert!(error.to_string().starts_with("5回試行しましたが失敗しました: "));
+
+        let io_error = std::fs::read_to_string("/nonexistent/fds/config.toml").context("読み込めません");
+        assert_eq!(Error::from(io_error.unwrap_err()).code(), "io");
+        assert_eq!(Error::from(anyhow::anyhow!("想定外")).code(), "other");
+    }
+}
@@ -1,23 +1,73 @@
 use anyhow::{anyhow, Result};
-use reqwest::Client;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::io::{BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, trace, warn};
 use url::Url;
 
+use crate::error::Error;
+use crate::logging::redact_body;
+
 const GOOGLE_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
 const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
 const REDIRECT_URI: &str = "http://localhost:8080/callback";
+// ブラウザでの認証を待つ時間の既定値
+pub const DEFAULT_AUTH_TIMEOUT_SECS: u64 = 300;
+// 接続してきたクライアントがリクエストを送ってくるまで待つ時間
+const REQUEST_READ_TIMEOUT_SECS: u64 = 5;
+// 期限切れの直前に使い始めたリクエストが失敗しないよう、この秒数だけ早めに更新する
+const REFRESH_MARGIN_SECS: i64 = 60;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TokenInfo {
     pub access_token: String,
     pub refresh_token: Option<String>,
     pub expires_in: u64,
     pub token_type: String,
+    // アクセストークンの有効期限（以前の tokens.json には無い）
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+// ログなどに出てもトークンが漏れないよう、値は伏せる
+impl std::fmt::Debug for TokenInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenInfo")
+            .field("access_token", &"[REDACTED]")
+            .field("refresh_token", &self.refresh_token.as_ref().map(|_| "[REDACTED]"))
+            .field("expires_in", &self.expires_in)
+            .field("token_type", &self.token_type)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl TokenInfo {
+    fn from_response(response: TokenResponse, issued_at: DateTime<Utc>) -> Self {
+        Self {
+            expires_at: Some(issued_at + Duration::seconds(response.expires_in as i64)),
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            expires_in: response.expires_in,
+            token_type: response.token_type,
+        }
+    }
+
+    // 期限切れ、またはまもなく切れるか（期限が分からないトークンも更新が必要とみなす）
+    pub fn needs_refresh(&self, now: DateTime<Utc>) -> bool {
+        match self.expires_at {
+            Some(expires_at) => now + Duration::seconds(REFRESH_MARGIN_SECS) >= expires_at,
+            None => true,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct TokenResponse {
     access_token: String,
     refresh_token: Option<String>,
@@ -29,6 +79,46 @@ pub struct OAuth2Client {
     client_id: String,
     client_secret: String,
     client: Client,
+    // ブラウザでの認証（コールバック）を待つ時間
+    callback_timeout: std::time::Duration,
+}
+
+// 認証リクエストごとに作る、PKCE の検証子と CSRF 対策の state
+struct AuthSession {
+    code_verifier: String,
+    state: String,
+}
+
+// コールバックのリクエストを解釈した結果
+#[derive(Debug, PartialEq)]
+enum CallbackResult {
+    Code(String),
+    // ユーザーが拒否した場合など、Google から error が返された
+    Denied(String),
+    // 関係のないリクエストや state の一致しないリクエスト（無視して待ち続ける）
+    Invalid(&'static str),
+}
+
+impl AuthSession {
+    fn new() -> Self {
+        Self {
+            code_verifier: random_token(),
+            state: random_token(),
+        }
+    }
+
+    // S256: 検証子の SHA-256 を base64url（パディングなし）にしたもの
+    fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.as_bytes()))
+    }
+}
+
+// 推測できない文字列（UUID v4 二つ分の乱数を base64url にした43文字）
+fn random_token() -> String {
+    let mut bytes = Vec::with_capacity(32);
+    bytes.extend_from_slice(uuid::Uuid::new_v4().as_bytes());
+    bytes.extend_from_slice(uuid::Uuid::new_v4().as_bytes());
+    URL_SAFE_NO_PAD.encode(bytes)
 }
 
 impl OAuth2Client {
@@ -37,30 +127,37 @@ impl OAuth2Client {
             client_id,
             client_secret,
             client: Client::new(),
+            callback_timeout: std::time::Duration::from_secs(DEFAULT_AUTH_TIMEOUT_SECS),
         }
     }
 
+    pub fn with_callback_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.callback_timeout = timeout;
+        self
+    }
+
     pub async fn authorize(&self) -> Result<TokenInfo> {
-        println!("Google Drive認証を開始します...");
+        info!("Google Drive認証を開始します...");
 
         // 認証URLを生成
-        let auth_url = self.build_auth_url()?;
-        println!("ブラウザで以下のURLを開いてください:");
-        println!("{}", auth_url);
+        let session = AuthSession::new();
+        let auth_url = self.build_auth_url(&session)?;
+        // 利用者が操作するための案内なので、ログの設定に関わらず表示する
+        eprintln!("ブラウザで以下のURLを開いてください:");
+        eprintln!("{}", auth_url);
 
         // ブラウザを開く
         if let Err(e) = open::that(&auth_url) {
-            println!("ブラウザの自動起動に失敗しました: {}", e);
-            println!("手動で上記URLをブラウザで開いてください。");
+            warn!("ブラウザの自動起動に失敗しました。手動で上記URLをブラウザで開いてください: {}", e);
         }
 
         // ローカルサーバーでコールバックを待機
-        let auth_code = self.wait_for_callback()?;
-        println!("認証コードを受信しました");
+        let auth_code = self.wait_for_callback(&session).await?;
+        debug!("認証コードを受信しました");
 
         // トークンを取得
-        let token_info = self.exchange_code_for_token(&auth_code).await?;
-        println!("アクセストークンを取得しました");
+        let token_info = self.exchange_code_for_token(&auth_code, &session).await?;
+        info!("アクセストークンを取得しました");
 
         Ok(token_info)
     }
@@ -73,28 +170,26 @@ impl OAuth2Client {
             ("grant_type", &"refresh_token".to_string()),
         ];
 
-        let response = self
-            .client
-            .post(GOOGLE_TOKEN_URL)
-            .form(&params)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow!("トークンの更新に失敗しました: {}", error_text));
+        let (status, body) = self.post_token_request(&params).await?;
+        if !status.is_success() {
+            let message = format!("トークンの更新に失敗しました: {}", body);
+            // invalid_grant（失効・取り消し）は認証し直すまで回復しない
+            if status == StatusCode::BAD_REQUEST || status == StatusCode::UNAUTHORIZED {
+                return Err(Error::AuthExpired(message).into());
+            }
+            return Err(anyhow!(message));
         }
 
-        let token_response: TokenResponse = response.json().await?;
-        Ok(TokenInfo {
-            access_token: token_response.access_token,
-            refresh_token: Some(refresh_token.to_string()), // 既存のリフレッシュトークンを保持
-            expires_in: token_response.expires_in,
-            token_type: token_response.token_type,
-        })
+        let token_response: TokenResponse = serde_json::from_str(&body)?;
+        let mut token_info = TokenInfo::from_response(token_response, Utc::now());
+        // 新しいリフレッシュトークンが返されなければ既存のものを使い続ける
+        if token_info.refresh_token.is_none() {
+            token_info.refresh_token = Some(refresh_token.to_string());
+        }
+        Ok(token_info)
     }
 
-    fn build_auth_url(&self) -> Result<String> {
+    fn build_auth_url(&self, session: &AuthSession) -> Result<String> {
         let mut url = Url::parse(GOOGLE_AUTH_URL)?;
         url.query_pairs_mut()
             .append_pair("client_id", &self.client_id)
@@ -102,79 +197,173 @@ impl OAuth2Client {
             .append_pair("response_type", "code")
             .append_pair("scope", "https://www.googleapis.com/auth/drive.readonly https://www.googleapis.com/auth/drive.metadata.readonly")
             .append_pair("access_type", "offline")
-            .append_pair("prompt", "consent");
+            .append_pair("prompt", "consent")
+            .append_pair("state", &session.state)
+            .append_pair("code_challenge", &session.code_challenge())
+            .append_pair("code_challenge_method", "S256");
 
         Ok(url.to_string())
     }
 
-    fn wait_for_callback(&self) -> Result<String> {
-        let listener = TcpListener::bind("127.0.0.1:8080")?;
-        println!("認証コールバックを待機中...");
+    async fn wait_for_callback(&self, session: &AuthSession) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:8080").await
+            .map_err(|e| anyhow!("認証用のポート 8080 を使用できません: {}", e))?;
+        info!("認証コールバックを待機中...（{}秒でタイムアウトします）", self.callback_timeout.as_secs());
 
-        for stream in listener.incoming() {
-            let stream = stream?;
-            if let Some(code) = self.handle_callback(stream)? {
-                return Ok(code);
+        let wait = async {
+            loop {
+                let (stream, _) = listener.accept().await?;
+                match Self::handle_callback(stream, &session.state).await {
+                    Ok(CallbackResult::Code(code)) => return Ok(code),
+                    Ok(CallbackResult::Denied(error)) => {
+                        return Err(Error::AuthRequired(format!("認証が拒否されました: {}", error)).into());
+                    }
+                    Ok(CallbackResult::Invalid(reason)) => warn!("無効なコールバックを無視しました: {}", reason),
+                    Err(e) => warn!("コールバックの処理に失敗しました: {}", e),
+                }
             }
-        }
+        };
 
-        Err(anyhow!("認証コードの取得に失敗しました"))
+        tokio::time::timeout(self.callback_timeout, wait).await.map_err(|_| {
+            Error::AuthRequired(format!(
+                "{}秒以内に認証が完了しませんでした。もう一度お試しください",
+                self.callback_timeout.as_secs()
+            ))
+        })?
     }
 
-    fn handle_callback(&self, mut stream: TcpStream) -> Result<Option<String>> {
-        let buf_reader = BufReader::new(&mut stream);
-        let request_line = buf_reader.lines().next().unwrap()?;
-
-        if let Some(query_start) = request_line.find("?") {
-            let query = &request_line[query_start + 1..];
-            if let Some(path_end) = query.find(" ") {
-                let query = &query[..path_end];
-                let url = Url::parse(&format!("http://localhost:8080/callback?{}", query))?;
-
-                for (key, value) in url.query_pairs() {
-                    if key == "code" {
-                        // 成功レスポンスを送信
-                        let response = "HTTP/1.1 200 OK\r\n\r\n<html><body><h1>認証が完了しました！</h1><p>このタブを閉じてください。</p></body></html>";
-                        stream.write_all(response.as_bytes())?;
-                        return Ok(Some(value.to_string()));
-                    }
-                }
-            }
-        }
+    async fn handle_callback(mut stream: TcpStream, expected_state: &str) -> Result<CallbackResult> {
+        // 何も送らない接続や壊れたリクエストでも、待機を止めない
+        let mut request_line = String::new();
+        let mut reader = BufReader::new(&mut stream);
+        let read = reader.read_line(&mut request_line);
+        let result = match tokio::time::timeout(std::time::Duration::from_secs(REQUEST_READ_TIMEOUT_SECS), read).await {
+            Ok(Ok(_)) => parse_callback(&request_line, expected_state),
+            Ok(Err(_)) | Err(_) => CallbackResult::Invalid("リクエストを読み取れません"),
+        };
 
-        // エラーレスポンスを送信
-        let response = "HTTP/1.1 400 Bad Request\r\n\r\n<html><body><h1>認証エラー</h1></body></html>";
-        stream.write_all(response.as_bytes())?;
-        Ok(None)
+        let response = match &result {
+            CallbackResult::Code(_) => "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nConnection: close\r\n\r\n<html><body><h1>認証が完了しました！</h1><p>このタブを閉じてください。</p></body></html>",
+            CallbackResult::Denied(_) => "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nConnection: close\r\n\r\n<html><body><h1>認証がキャンセルされました</h1><p>このタブを閉じてください。</p></body></html>",
+            CallbackResult::Invalid(_) => "HTTP/1.1 400 Bad Request\r\nContent-Type: text/html; charset=utf-8\r\nConnection: close\r\n\r\n<html><body><h1>認証エラー</h1></body></html>",
+        };
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(result)
     }
 
-    async fn exchange_code_for_token(&self, code: &str) -> Result<TokenInfo> {
+    async fn exchange_code_for_token(&self, code: &str, session: &AuthSession) -> Result<TokenInfo> {
         let params = [
             ("client_id", &self.client_id),
             ("client_secret", &self.client_secret),
             ("code", &code.to_string()),
+            ("code_verifier", &session.code_verifier),
             ("grant_type", &"authorization_code".to_string()),
             ("redirect_uri", &REDIRECT_URI.to_string()),
         ];
 
-        let response = self
-            .client
-            .post(GOOGLE_TOKEN_URL)
-            .form(&params)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow!("トークンの取得に失敗しました: {}", error_text));
+        let (status, body) = self.post_token_request(&params).await?;
+        if !status.is_success() {
+            return Err(anyhow!("トークンの取得に失敗しました: {}", body));
         }
 
-        let token_response: TokenResponse = response.json().await?;
-        Ok(TokenInfo {
-            access_token: token_response.access_token,
-            refresh_token: token_response.refresh_token,
-            expires_in: token_response.expires_in,
-            token_type: token_response.token_type,
-        })
+        let token_response: TokenResponse = serde_json::from_str(&body)?;
+        Ok(TokenInfo::from_response(token_response, Utc::now()))
+    }
+
+    // トークンエンドポイントに送り、ステータスと本文を返す（本文のトークンは伏せてログに出す）
+    async fn post_token_request(&self, params: &[(&str, &String)]) -> Result<(StatusCode, String)> {
+        let grant_type = params.iter().find(|(key, _)| *key == "grant_type").map(|(_, value)| value.as_str());
+        debug!(grant_type, "トークンを要求します");
+
+        let response = self.client.post(GOOGLE_TOKEN_URL).form(params).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        trace!(%status, body = %redact_body(&body), "トークンエンドポイントの応答");
+        Ok((status, body))
+    }
+}
+
+// リクエスト行（GET /callback?code=...&state=... HTTP/1.1）を解釈する
+fn parse_callback(request_line: &str, expected_state: &str) -> CallbackResult {
+    let mut parts = request_line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return CallbackResult::Invalid("GET リクエストではありません");
+    };
+    let Ok(url) = Url::parse(&format!("http://localhost{}", target)) else {
+        return CallbackResult::Invalid("URLを解釈できません");
+    };
+    if url.path() != "/callback" {
+        return CallbackResult::Invalid("コールバック以外のパスです");
+    }
+
+    let param = |name: &str| url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+    // state が一致しないものは、他のページから送られた偽のコールバックかもしれないので使わない
+    if param("state").as_deref() != Some(expected_state) {
+        return CallbackResult::Invalid("state が一致しません");
+    }
+
+    if let Some(error) = param("error") {
+        return CallbackResult::Denied(error);
+    }
+    match param("code") {
+        Some(code) if !code.is_empty() => CallbackResult::Code(code),
+        _ => CallbackResult::Invalid("認証コードがありません"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_expiry_from_issue_time() {
+        let issued_at = Utc::now();
+        let tokens = TokenInfo::from_response(TokenResponse {
+            access_token: "access".to_string(),
+            refresh_token: None,
+            expires_in: 3600,
+            token_type: "Bearer".to_string(),
+        }, issued_at);
+
+        assert!(!tokens.needs_refresh(issued_at));
+        assert!(tokens.needs_refresh(issued_at + Duration::minutes(59) + Duration::seconds(30)));
+
+        // 期限を保存していない以前の tokens.json も読み込め、更新が必要とみなす
+        let legacy: TokenInfo = serde_json::from_str(
+            r#"{"access_token":"a","refresh_token":"r","expires_in":3599,"token_type":"Bearer"}"#,
+        ).unwrap();
+        assert!(legacy.expires_at.is_none());
+        assert!(legacy.needs_refresh(issued_at));
+    }
+
+    #[test]
+    fn derives_s256_code_challenge() {
+        // SHA-256("abc") = ba7816bf... を、パディングなしの base64url にしたもの
+        let session = AuthSession {
+            code_verifier: "abc".to_string(),
+            state: String::new(),
+        };
+        assert_eq!(session.code_challenge(), "ungWv48Bz-pBQUDeXa4iI7ADYaOWF3qctBD_YfIAFa0");
+
+        let session = AuthSession::new();
+        assert_eq!(session.code_verifier.len(), 43);
+        assert_ne!(session.code_verifier, session.state);
+    }
+
+    #[test]
+    fn checks_state_and_errors_in_callbacks() {
+        assert_eq!(
+            parse_callback("GET /callback?code=abc&state=s1 HTTP/1.1\r\n", "s1"),
+            CallbackResult::Code("abc".to_string())
+        );
+        assert_eq!(
+            parse_callback("GET /callback?error=access_denied&state=s1 HTTP/1.1", "s1"),
+            CallbackResult::Denied("access_denied".to_string())
+        );
+        assert!(matches!(parse_callback("GET /callback?code=abc&state=other HTTP/1.1", "s1"), CallbackResult::Invalid(_)));
+        assert!(matches!(parse_callback("GET /callback?code=abc HTTP/1.1", "s1"), CallbackResult::Invalid(_)));
+        assert!(matches!(parse_callback("GET /favicon.ico HTTP/1.1", "s1"), CallbackResult::Invalid(_)));
+        assert!(matches!(parse_callback("", "s1"), CallbackResult::Invalid(_)));
     }
-}
\ No newline at end of file
+}
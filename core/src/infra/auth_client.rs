@@ -0,0 +1,255 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use reqwest::{Client, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::{debug, trace, warn};
+
+use super::auth::{OAuth2Client, TokenInfo};
+use super::config::{AppConfig, ConfigManager};
+use super::retry::{random_jitter, retry_after, DriveApiError, RetryPolicy};
+use crate::error::Error;
+use crate::logging::{redact_body, redact_url};
+
+// 認証付きで Google API を呼び出すクライアント
+// 期限が近いアクセストークンは送信前に更新し、401 が返った場合も一度だけ更新して再送する
+// 更新したトークンは tokens.json に保存する
+pub struct AuthenticatedClient {
+    client: Client,
+    oauth_client: OAuth2Client,
+    config_manager: ConfigManager,
+    retry_policy: RetryPolicy,
+    // 同時に複数のリクエストが更新しないよう、更新中はロックを保持する
+    tokens: Mutex<TokenInfo>,
+}
+
+impl AuthenticatedClient {
+    pub fn new(config_manager: ConfigManager, config: &AppConfig, tokens: TokenInfo) -> Self {
+        Self {
+            client: Client::new(),
+            oauth_client: config.oauth_client(),
+            config_manager,
+            retry_policy: RetryPolicy::default(),
+            tokens: Mutex::new(tokens),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    // 現在のトークン（更新されていれば更新後のもの）
+    pub async fn tokens(&self) -> TokenInfo {
+        self.tokens.lock().await.clone()
+    }
+
+    // 送信に使うアクセストークン。期限切れが近く、リフレッシュトークンがあれば先に更新する
+    pub async fn access_token(&self) -> Result<String> {
+        let mut tokens = self.tokens.lock().await;
+        if tokens.refresh_token.is_some() && tokens.needs_refresh(Utc::now()) {
+            self.refresh_locked(&mut tokens).await?;
+        }
+        Ok(tokens.access_token.clone())
+    }
+
+    // rejected_token が拒否されたので更新する（他のリクエストが更新済みなら何もしない）
+    async fn refresh_rejected(&self, rejected_token: &str) -> Result<()> {
+        let mut tokens = self.tokens.lock().await;
+        if tokens.access_token != rejected_token {
+            return Ok(());
+        }
+        if tokens.refresh_token.is_none() {
+            return Err(Error::AuthExpired(
+                "アクセストークンが無効で、リフレッシュトークンがありません。fuzzy-drive-search auth login --force で認証し直してください".to_string(),
+            ).into());
+        }
+        self.refresh_locked(&mut tokens).await
+    }
+
+    async fn refresh_locked(&self, tokens: &mut TokenInfo) -> Result<()> {
+        let refresh_token = tokens.refresh_token.clone()
+            .ok_or_else(|| anyhow!("リフレッシュトークンがありません"))?;
+        let new_tokens = self.oauth_client.refresh_token(&refresh_token).await
+            .map_err(|e| e.context("認証トークンを更新できません"))?;
+
+        self.config_manager.save_tokens(&new_tokens)?;
+        *tokens = new_tokens;
+        Ok(())
+    }
+
+    // 認証付きの GET の本文を返す
+    // 401 はトークンを更新して一度だけ再送し、レート制限や一時的な障害は待ってから再試行する
+    pub async fn get_text<Q: Serialize + ?Sized>(&self, url: &str, query: &Q) -> Result<String> {
+        let mut refreshed = false;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let access_token = self.access_token().await?;
+
+            let (error, retry_after): (anyhow::Error, Option<std::time::Duration>) =
+                match self.send_get(url, query, &access_token).await {
+                    Ok(response) if response.status().is_success() => match response.text().await {
+                        Ok(body) => {
+                            trace!(body = %redact_body(&body), "Drive API の応答本文");
+                            return Ok(body);
+                        }
+                        // 本文の途中で切断された場合も一時的な障害として扱う
+                        Err(e) => (e.into(), None),
+                    },
+                    Ok(response) if response.status() == StatusCode::UNAUTHORIZED && !refreshed => {
+                        self.refresh_rejected(&access_token).await?;
+                        refreshed = true;
+                        attempt -= 1;
+                        continue;
+                    }
+                    Ok(response) => {
+                        let status = response.status();
+                        let retry_after = retry_after(response.headers(), Utc::now());
+                        let body = response.text().await.unwrap_or_default();
+                        let error = DriveApiError::from_response(status, &body);
+                        if !error.class.is_retryable() {
+                            return Err(error.into());
+                        }
+                        (error.into(), retry_after)
+                    }
+                    Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => (e.into(), None),
+                    Err(e) => return Err(e.into()),
+                };
+
+            if attempt >= self.retry_policy.max_attempts {
+                return Err(error.context(format!("{}回試行しましたが失敗しました", attempt)));
+            }
+
+            let Some(delay) = self.retry_policy.delay(attempt, retry_after, random_jitter()) else {
+                return Err(error.context(format!(
+                    "再試行まで{}秒待つよう指示されたため、再試行しません",
+                    retry_after.unwrap_or_default().as_secs()
+                )));
+            };
+            warn!(
+                "{}。{:.1}秒後に再試行します（{}/{}回目）",
+                error,
+                delay.as_secs_f64(),
+                attempt + 1,
+                self.retry_policy.max_attempts
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    pub async fn get_json<T: DeserializeOwned, Q: Serialize + ?Sized>(&self, url: &str, query: &Q) -> Result<T> {
+        let body = self.get_text(url, query).await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    // Authorization ヘッダーはログに出さない
+    async fn send_get<Q: Serialize + ?Sized>(&self, url: &str, query: &Q, access_token: &str) -> reqwest::Result<Response> {
+        let request = self.client
+            .get(url)
+            .bearer_auth(access_token)
+            .query(query)
+            .build()?;
+        let url = redact_url(request.url());
+
+        let started = std::time::Instant::now();
+        let response = self.client.execute(request).await;
+        match &response {
+            Ok(response) => debug!(%url, status = %response.status(), elapsed_ms = started.elapsed().as_millis() as u64, "GET"),
+            Err(e) => debug!(%url, error = %e, elapsed_ms = started.elapsed().as_millis() as u64, "GET に失敗しました"),
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::retry::ErrorClass;
+    use crate::test_util::temp_dir;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // 接続ごとに responses を順に返すサーバーを立て、URLと受けたリクエスト数を返す
+    async fn serve(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn client() -> AuthenticatedClient {
+        // トークンを更新しない限り何も書き込まないので、作られたディレクトリはすぐ消してよい
+        let dir = temp_dir();
+        let config_manager = ConfigManager::with_config_dir(dir.path().to_path_buf()).unwrap();
+        let tokens = TokenInfo {
+            access_token: "access".to_string(),
+            refresh_token: None,
+            expires_in: 3600,
+            token_type: "Bearer".to_string(),
+            expires_at: Some(Utc::now() + chrono::Duration::hours(1)),
+        };
+        AuthenticatedClient::new(config_manager, &AppConfig::default(), tokens)
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(10),
+                max_retry_after: Duration::from_secs(1),
+            })
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits_and_server_errors() {
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 403 Forbidden\r\nContent-Length: 75\r\nConnection: close\r\n\r\n{\"error\":{\"message\":\"limit\",\"errors\":[{\"reason\":\"userRateLimitExceeded\"}]}}",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        ]).await;
+
+        let body = client().get_text(&url, &[("q", "x")]).await.unwrap();
+        assert_eq!(body, "ok");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_retry_after_exceeds_the_limit() {
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 3600\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]).await;
+
+        let error = client().get_text(&url, &[("q", "x")]).await.unwrap_err();
+        let api_error = error.downcast_ref::<DriveApiError>().unwrap();
+        assert_eq!(api_error.class, ErrorClass::RateLimited);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn fails_fast_on_permanent_errors() {
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 71\r\nConnection: close\r\n\r\n{\"error\":{\"message\":\"File not found\",\"errors\":[{\"reason\":\"notFound\"}]}}",
+        ]).await;
+
+        let error = client().get_text(&url, &[("q", "x")]).await.unwrap_err();
+        let api_error = error.downcast_ref::<DriveApiError>().unwrap();
+        assert_eq!(api_error.reason.as_deref(), Some("notFound"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
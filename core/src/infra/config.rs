@@ -1,16 +1,55 @@
 use anyhow::Result;
 use dirs::config_dir;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use tracing::{debug, info};
 
-use super::auth::TokenInfo;
+use super::auth::{OAuth2Client, TokenInfo, DEFAULT_AUTH_TIMEOUT_SECS};
+use super::fs_util::write_atomic;
+use crate::error::Error;
+
+pub const DEFAULT_SYNC_CONCURRENCY: usize = 8;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppConfig {
     pub target_folder_ids: Vec<String>,
     pub google_client_id: String,
     pub google_client_secret: String,
+    // フォルダIDごとの取得設定（未設定のフォルダは直下のみ取得）
+    #[serde(default)]
+    pub folder_settings: HashMap<String, FolderSettings>,
+    // ファイル一覧の保存形式（"json" または "sqlite"）
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+    // open --app で使う、種類（document / spreadsheet / presentation）ごとのデスクトップアプリ用URL
+    // {id} はファイルID、{url} はブラウザ用のURLに置き換える
+    #[serde(default)]
+    pub app_url_templates: HashMap<String, String>,
+    // ブラウザでの認証を待つ秒数（未指定なら300秒）
+    #[serde(default)]
+    pub auth_timeout_secs: Option<u64>,
+    // 同期時に Drive API へ同時に送るリクエストの上限（未指定なら8）
+    #[serde(default)]
+    pub sync_concurrency: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Json,
+    Sqlite,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct FolderSettings {
+    // サブフォルダを再帰的に取得するか
+    #[serde(default)]
+    pub recursive: bool,
+    // 対象フォルダから何階層下まで辿るか（未指定なら無制限）
+    pub max_depth: Option<u32>,
 }
 
 impl Default for AppConfig {
@@ -19,23 +58,100 @@ impl Default for AppConfig {
             target_folder_ids: vec![],
             google_client_id: "your_client_id_here".to_string(),
             google_client_secret: "your_client_secret_here".to_string(),
+            folder_settings: HashMap::new(),
+            storage_backend: StorageBackend::default(),
+            app_url_templates: HashMap::new(),
+            auth_timeout_secs: None,
+            sync_concurrency: None,
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn folder_settings_for(&self, folder_id: &str) -> FolderSettings {
+        self.folder_settings.get(folder_id).cloned().unwrap_or_default()
+    }
+
+    // 指定したフォルダ（対象フォルダからの経路）の下をさらに辿るか
+    pub fn can_descend(&self, folder_path: &[String]) -> bool {
+        let Some(root_id) = folder_path.first() else {
+            return false;
+        };
+        let settings = self.folder_settings_for(root_id);
+        let depth = (folder_path.len() - 1) as u32;
+        settings.recursive && depth < settings.max_depth.unwrap_or(u32::MAX)
+    }
+
+    pub fn sync_concurrency(&self) -> usize {
+        self.sync_concurrency.unwrap_or(DEFAULT_SYNC_CONCURRENCY).max(1)
+    }
+
+    pub fn oauth_client(&self) -> OAuth2Client {
+        let timeout = self.auth_timeout_secs.unwrap_or(DEFAULT_AUTH_TIMEOUT_SECS);
+        OAuth2Client::new(self.google_client_id.clone(), self.google_client_secret.clone())
+            .with_callback_timeout(std::time::Duration::from_secs(timeout))
+    }
+
+    // 検索対象フォルダを追加（既にあれば取得設定だけ更新）し、新たに追加した場合は true を返す
+    pub fn add_folder(&mut self, folder_id: &str, settings: FolderSettings) -> bool {
+        let added = !self.target_folder_ids.iter().any(|id| id == folder_id);
+        if added {
+            self.target_folder_ids.push(folder_id.to_string());
+        }
+        if settings == FolderSettings::default() {
+            self.folder_settings.remove(folder_id);
+        } else {
+            self.folder_settings.insert(folder_id.to_string(), settings);
         }
+        added
+    }
+
+    // 検索対象フォルダとその取得設定を削除し、削除した場合は true を返す
+    pub fn remove_folder(&mut self, folder_id: &str) -> bool {
+        let before = self.target_folder_ids.len();
+        self.target_folder_ids.retain(|id| id != folder_id);
+        self.folder_settings.remove(folder_id);
+        self.target_folder_ids.len() != before
+    }
+
+    // 種類に対応するテンプレートがあれば、デスクトップアプリ用のURLを返す
+    pub fn app_url(&self, kind: &str, file_id: &str, web_view_link: &str) -> Option<String> {
+        self.app_url_templates.get(kind).map(|template| {
+            template.replace("{id}", file_id).replace("{url}", web_view_link)
+        })
+    }
+}
+
+// フォルダのURL（https://drive.google.com/drive/folders/<ID>?...）またはIDからフォルダIDを取り出す
+pub fn parse_folder_id(input: &str) -> String {
+    let input = input.trim();
+    match input.split_once("/folders/") {
+        Some((_, rest)) => rest.split(['?', '/', '#']).next().unwrap_or(rest).to_string(),
+        None => input.to_string(),
     }
 }
 
+#[derive(Clone)]
 pub struct ConfigManager {
     pub config_dir: PathBuf,
 }
 
 impl ConfigManager {
     pub fn new() -> Result<Self> {
-        let config_dir = config_dir()
+        Self::with_config_dir(Self::default_config_dir()?)
+    }
+
+    pub fn default_config_dir() -> Result<PathBuf> {
+        Ok(config_dir()
             .ok_or_else(|| anyhow::anyhow!("設定ディレクトリが見つかりません"))?
-            .join("fuzzy-drive-search");
+            .join("fuzzy-drive-search"))
+    }
 
+    // 設定ディレクトリを指定して使う（--config-dir や --profile）
+    pub fn with_config_dir(config_dir: PathBuf) -> Result<Self> {
         if !config_dir.exists() {
             fs::create_dir_all(&config_dir)?;
-            println!("設定ディレクトリを作成しました: {:?}", config_dir);
+            info!("設定ディレクトリを作成しました: {}", config_dir.display());
         }
 
         Ok(Self { config_dir })
@@ -45,22 +161,24 @@ impl ConfigManager {
         let config_path = self.config_dir.join("config.toml");
 
         if !config_path.exists() {
-            println!("設定ファイルが存在しません。初期設定を作成します。");
+            info!("設定ファイルが存在しません。初期設定を作成します。");
             let default_config = AppConfig::default();
             self.save_config(&default_config)?;
             return Ok(default_config);
         }
 
         let content = fs::read_to_string(&config_path)?;
-        let config: AppConfig = toml::from_str(&content)?;
+        let config: AppConfig = toml::from_str(&content).map_err(|e| {
+            Error::ConfigInvalid(format!("設定ファイルを読み込めません（{}）: {}", config_path.display(), e))
+        })?;
         Ok(config)
     }
 
     pub fn save_config(&self, config: &AppConfig) -> Result<()> {
         let config_path = self.config_dir.join("config.toml");
         let content = toml::to_string_pretty(config)?;
-        fs::write(&config_path, content)?;
-        println!("設定ファイルを保存しました: {:?}", config_path);
+        write_atomic(&config_path, content.as_bytes())?;
+        debug!("設定ファイルを保存しました: {}", config_path.display());
         Ok(())
     }
 
@@ -72,19 +190,29 @@ impl ConfigManager {
         }
 
         let content = fs::read_to_string(&tokens_path)?;
-        let tokens: TokenInfo = serde_json::from_str(&content)?;
+        let tokens: TokenInfo = serde_json::from_str(&content).map_err(|e| {
+            Error::AuthRequired(format!("保存済みの認証トークンを読み込めません（{}）: {}", tokens_path.display(), e))
+        })?;
         Ok(Some(tokens))
     }
 
     pub fn save_tokens(&self, tokens: &TokenInfo) -> Result<()> {
         let tokens_path = self.config_dir.join("tokens.json");
         let content = serde_json::to_string_pretty(tokens)?;
-        fs::write(&tokens_path, content)?;
-        println!("認証トークンを保存しました");
+        write_atomic(&tokens_path, content.as_bytes())?;
+        debug!("認証トークンを保存しました");
         Ok(())
     }
 
-
+    // 保存済みのトークンを削除する（削除した場合は true）
+    pub fn delete_tokens(&self) -> Result<bool> {
+        let tokens_path = self.config_dir.join("tokens.json");
+        match fs::remove_file(&tokens_path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
 
     pub fn setup_initial_config(&self) -> Result<AppConfig> {
         self.setup_initial_config_with_overrides(None, None)
@@ -95,42 +223,77 @@ impl ConfigManager {
         client_id_override: Option<String>,
         client_secret_override: Option<String>
     ) -> Result<AppConfig> {
-        println!("初期設定を開始します。");
+        debug!("初期設定を開始します。");
         
         let mut config = self.load_config()?;
 
         // コマンドライン引数からの上書き
+        // トークンの更新にも同じクライアントを使うので、設定ファイルに保存する
+        let mut overridden = false;
         if let Some(client_id) = client_id_override {
+            overridden |= config.google_client_id != client_id;
             config.google_client_id = client_id;
-            println!("Client IDをコマンドライン引数から設定しました");
+            debug!("Client IDをコマンドライン引数から設定しました");
         }
         if let Some(client_secret) = client_secret_override {
+            overridden |= config.google_client_secret != client_secret;
             config.google_client_secret = client_secret;
-            println!("Client Secretをコマンドライン引数から設定しました");
+            debug!("Client Secretをコマンドライン引数から設定しました");
+        }
+        if overridden {
+            self.save_config(&config)?;
         }
 
         // Google API認証情報の設定確認
+        // 設定手順の案内は利用者向けなので、ログの設定に関わらず表示する
         if config.google_client_id == "your_client_id_here" {
-            println!("\nGoogle Drive API の設定が必要です。");
-            println!("Google Cloud Console でプロジェクトを作成し、Drive API を有効にしてください。");
-            println!("OAuth 2.0 クライアントIDとシークレットを取得してください。");
-            println!("\n設定ファイルを編集してください: {:?}", self.config_dir.join("config.toml"));
-            println!("client_id と client_secret を正しい値に変更した後、再度実行してください。");
-            return Err(anyhow::anyhow!("Google API認証情報の設定が必要です"));
+            eprintln!("\nGoogle Drive API の設定が必要です。");
+            eprintln!("Google Cloud Console でプロジェクトを作成し、Drive API を有効にしてください。");
+            eprintln!("OAuth 2.0 クライアントIDとシークレットを取得してください。");
+            eprintln!("\n設定ファイルを編集してください: {:?}", self.config_dir.join("config.toml"));
+            eprintln!("client_id と client_secret を正しい値に変更した後、再度実行してください。");
+            return Err(Error::ConfigMissing("Google API認証情報の設定が必要です".to_string()).into());
         }
 
         // 検索対象フォルダIDの設定確認
         if config.target_folder_ids.is_empty() {
-            println!("\n検索対象のGoogle Driveフォルダを設定してください。");
-            println!("フォルダのURLから ID を取得してください。");
-            println!("例: https://drive.google.com/drive/folders/1ABCDefGHijKLmnOPqrStUVwxyz");
-            println!("この場合、フォルダID は「1ABCDefGHijKLmnOPqrStUVwxyz」です。");
-            println!("\n設定ファイルを編集してください: {:?}", self.config_dir.join("config.toml"));
-            println!("target_folder_ids を配列で設定した後、再度実行してください。");
-            println!("例: target_folder_ids = [\"1ABCDefGHijKLmnOPqrStUVwxyz\", \"1XYZabcdefghijklmnopqrst\"]");
-            return Err(anyhow::anyhow!("検索対象フォルダIDの設定が必要です"));
+            eprintln!("\n検索対象のGoogle Driveフォルダを設定してください。");
+            eprintln!("フォルダのURLから ID を取得してください。");
+            eprintln!("例: https://drive.google.com/drive/folders/1ABCDefGHijKLmnOPqrStUVwxyz");
+            eprintln!("この場合、フォルダID は「1ABCDefGHijKLmnOPqrStUVwxyz」です。");
+            eprintln!("\n設定ファイルを編集してください: {:?}", self.config_dir.join("config.toml"));
+            eprintln!("target_folder_ids を配列で設定した後、再度実行してください。");
+            eprintln!("例: target_folder_ids = [\"1ABCDefGHijKLmnOPqrStUVwxyz\", \"1XYZabcdefghijklmnopqrst\"]");
+            eprintln!("\nサブフォルダも検索対象にする場合は、フォルダごとに設定を追加してください。");
+            eprintln!("例: [folder_settings.1ABCDefGHijKLmnOPqrStUVwxyz]");
+            eprintln!("    recursive = true");
+            eprintln!("    max_depth = 3");
+            return Err(Error::ConfigMissing("検索対象フォルダIDの設定が必要です".to_string()).into());
         }
 
         Ok(config)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn saves_client_overrides_from_init() {
+        let dir = temp_dir();
+        let config_manager = ConfigManager::with_config_dir(dir.path().to_path_buf()).unwrap();
+
+        // 検索対象フォルダが未設定でも、指定されたクライアントは保存される
+        let result = config_manager.setup_initial_config_with_overrides(
+            Some("id.apps.googleusercontent.com".to_string()),
+            Some("secret".to_string()),
+        );
+        assert!(result.is_err());
+
+        let config = config_manager.load_config().unwrap();
+        assert_eq!(config.google_client_id, "id.apps.googleusercontent.com");
+        assert_eq!(config.google_client_secret, "secret");
+    }
+}
@@ -1,6 +1,18 @@
 use anyhow::Result;
-use reqwest::Client;
+use chrono::Utc;
 use serde::Deserialize;
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::sync::Semaphore;
+use tracing::info;
+
+use super::auth_client::AuthenticatedClient;
+use super::retry::DriveApiError;
+use crate::models::{DriveFile, FileOwner, ProgressSender, SyncProgress};
+
+pub const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+pub const SHORTCUT_MIME_TYPE: &str = "application/vnd.google-apps.shortcut";
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DriveApiFile {
@@ -13,6 +25,104 @@ pub struct DriveApiFile {
     #[serde(rename = "mimeType")]
     pub mime_type: String,
     pub parents: Option<Vec<String>>,
+    #[serde(default)]
+    pub trashed: bool,
+    #[serde(rename = "shortcutDetails")]
+    pub shortcut_details: Option<ShortcutDetails>,
+    #[serde(default)]
+    pub owners: Vec<DriveApiOwner>,
+    // クロール時に辿ったフォルダIDの経路（APIレスポンスには含まれない）
+    #[serde(skip)]
+    pub folder_path: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShortcutDetails {
+    #[serde(rename = "targetId")]
+    pub target_id: String,
+    #[serde(rename = "targetMimeType")]
+    pub target_mime_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DriveApiOwner {
+    #[serde(rename = "displayName", default)]
+    pub display_name: String,
+    #[serde(rename = "emailAddress", default)]
+    pub email_address: String,
+}
+
+// クロールの起点となる対象フォルダ
+#[derive(Debug, Clone)]
+pub struct FolderCrawlRoot {
+    pub folder_id: String,
+    pub recursive: bool,
+    pub max_depth: Option<u32>,
+}
+
+// 取得待ちのフォルダ
+struct PendingFolder {
+    folder_id: String,
+    folder_path: Vec<String>,
+    depth: u32,
+    // 起点の対象フォルダから何階層下まで辿るか
+    max_depth: u32,
+}
+
+// get_folder_info で取得するフォルダの情報
+#[derive(Debug, Deserialize, Clone)]
+pub struct FolderInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+}
+
+#[derive(Debug, Default)]
+pub struct FolderCrawl {
+    pub files: Vec<DriveApiFile>,
+    // 辿ったフォルダIDごとの、対象フォルダからの経路（自身を含む）
+    pub folder_paths: HashMap<String, Vec<String>>,
+    // 辿ったサブフォルダの名前（対象フォルダ自身は含まない）
+    pub folder_names: HashMap<String, String>,
+}
+
+impl DriveApiFile {
+    pub fn is_folder(&self) -> bool {
+        self.mime_type == FOLDER_MIME_TYPE
+    }
+
+    // フォルダ、またはフォルダを指すショートカットなら辿り先のフォルダIDを返す
+    pub fn folder_target_id(&self) -> Option<&str> {
+        if self.is_folder() {
+            return Some(&self.id);
+        }
+        if self.mime_type == SHORTCUT_MIME_TYPE {
+            if let Some(ref details) = self.shortcut_details {
+                if details.target_mime_type.as_deref() == Some(FOLDER_MIME_TYPE) {
+                    return Some(&details.target_id);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn into_drive_file(self) -> Result<DriveFile> {
+        Ok(DriveFile::new(
+            self.id,
+            self.name,
+            self.web_view_link.unwrap_or_default(),
+            chrono::DateTime::parse_from_rfc3339(&self.modified_time)?
+                .with_timezone(&Utc),
+            self.mime_type,
+            self.parents.unwrap_or_default(),
+        )
+        .with_folder_path(self.folder_path)
+        .with_owners(self.owners.into_iter().map(|owner| FileOwner {
+            display_name: owner.display_name,
+            email_address: owner.email_address,
+        }).collect()))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -22,96 +132,161 @@ pub struct DriveFilesResponse {
     pub next_page_token: Option<String>,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct DriveChange {
+    #[serde(rename = "fileId")]
+    pub file_id: String,
+    #[serde(default)]
+    pub removed: bool,
+    pub file: Option<DriveApiFile>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DriveChangesResponse {
+    #[serde(default)]
+    pub changes: Vec<DriveChange>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+    #[serde(rename = "newStartPageToken")]
+    pub new_start_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StartPageTokenResponse {
+    #[serde(rename = "startPageToken")]
+    start_page_token: String,
+}
+
 pub struct GoogleDriveClient {
-    client: Client,
-    access_token: String,
+    http: AuthenticatedClient,
 }
 
 impl GoogleDriveClient {
-    pub fn new(access_token: String) -> Self {
-        Self {
-            client: Client::new(),
-            access_token,
-        }
+    pub fn new(http: AuthenticatedClient) -> Self {
+        Self { http }
     }
 
-    pub async fn list_files_in_folder(&self, _folder_id: &str, page_token: Option<String>) -> Result<DriveFilesResponse> {
-        // 全ファイルを取得（フォルダも含む）してから階層的にフィルタリング
-        let query = "trashed=false".to_string();
-        
-        println!("検索クエリ: {} (全ファイルを取得後、フォルダ階層でフィルタリング)", query);
-        
-        let mut params = vec![
-            ("fields", "files(id,name,webViewLink,modifiedTime,mimeType,parents),nextPageToken"),
-            ("pageSize", "1000"),
-            ("q", query.as_str()),
-            ("supportsAllDrives", "true"),
-            ("includeItemsFromAllDrives", "true"),
-            ("corpora", "allDrives"),
-        ];
-        
-        if let Some(ref token) = page_token {
-            params.push(("pageToken", token));
-        }
-
-        let response = self
-            .client
-            .get("https://www.googleapis.com/drive/v3/files")
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .query(&params)
-            .send()
-            .await?;
+    pub fn http(&self) -> &AuthenticatedClient {
+        &self.http
+    }
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            anyhow::bail!("Drive API エラー: {}", error_text);
-        }
+    // 対象フォルダ（設定に応じてサブフォルダも）のファイルを取得する
+    // フォルダごとの一覧取得を requests の空きの数まで同時に行い、見つかったサブフォルダは空き次第取得を始める
+    pub async fn list_files_in_folders(
+        &self,
+        roots: &[FolderCrawlRoot],
+        requests: &Semaphore,
+        progress: &ProgressSender,
+    ) -> Result<FolderCrawl> {
+        let mut crawl = FolderCrawl::default();
+        // 複数の親を持つフォルダやショートカットによる循環を避けるため、訪問済みを記録する
+        let mut visited_folders: HashSet<String> = HashSet::new();
+        let mut seen_files: HashSet<String> = HashSet::new();
 
-        let response_text = response.text().await?;
-        println!("APIレスポンス（最初の500文字）: {}", &response_text[..response_text.len().min(500)]);
-        
-        let files_response: DriveFilesResponse = serde_json::from_str(&response_text)?;
-        println!("取得したファイル数: {}", files_response.files.len());
-        
-        Ok(files_response)
-    }
+        info!("{}個のフォルダのファイルを取得中...", roots.len());
 
-    pub async fn list_files_in_folders_directly(&self, folder_ids: &[String]) -> Result<Vec<DriveApiFile>> {
-        let mut all_files = Vec::new();
+        let mut pending = VecDeque::new();
+        for root in roots {
+            if visited_folders.insert(root.folder_id.clone()) {
+                pending.push_back(PendingFolder {
+                    folder_id: root.folder_id.clone(),
+                    folder_path: vec![root.folder_id.clone()],
+                    depth: 0,
+                    max_depth: if root.recursive { root.max_depth.unwrap_or(u32::MAX) } else { 0 },
+                });
+            }
+        }
 
-        println!("{}個のフォルダの直下ファイルを取得中...", folder_ids.len());
+        let mut in_flight = FuturesUnordered::new();
+        loop {
+            while let Some(folder) = pending.pop_front() {
+                // 取得中のものが無いときは空きを待つ（他の処理と共有しているので、そちらが終われば空く）
+                let permit = if in_flight.is_empty() {
+                    requests.acquire().await?
+                } else if let Ok(permit) = requests.try_acquire() {
+                    permit
+                } else {
+                    pending.push_front(folder);
+                    break;
+                };
+                progress.send(SyncProgress::FolderStarted {
+                    folder_id: folder.folder_id.clone(),
+                    depth: folder.depth,
+                });
+                in_flight.push(async move {
+                    let files = self.list_all_folder_contents(&folder.folder_id, progress).await;
+                    drop(permit);
+                    (folder, files)
+                });
+            }
 
-        for (index, folder_id) in folder_ids.iter().enumerate() {
-            println!("フォルダ {}/{}: {} の直下ファイルを取得中...", 
-                index + 1, folder_ids.len(), folder_id);
+            // 一つでも失敗したら、取得中のものは破棄して同期全体を失敗させる（一時的な失敗は再試行済み）
+            let Some((folder, files)) = in_flight.next().await else {
+                break;
+            };
+            let files = files?;
+            crawl.folder_paths.insert(folder.folder_id.clone(), folder.folder_path.clone());
 
-            let mut page_token = None;
-            loop {
-                let response = self.list_folder_contents(folder_id, page_token).await?;
-                
-                for file in response.files {
-                    // ファイルのみを追加（フォルダは除外）
-                    if file.mime_type != "application/vnd.google-apps.folder" {
-                        all_files.push(file);
+            for mut file in files {
+                if let Some(target_id) = file.folder_target_id() {
+                    if folder.depth < folder.max_depth && visited_folders.insert(target_id.to_string()) {
+                        crawl.folder_names.insert(target_id.to_string(), file.name.clone());
+                        let mut child_path = folder.folder_path.clone();
+                        child_path.push(target_id.to_string());
+                        pending.push_back(PendingFolder {
+                            folder_id: target_id.to_string(),
+                            folder_path: child_path,
+                            depth: folder.depth + 1,
+                            max_depth: folder.max_depth,
+                        });
                     }
+                    continue;
                 }
 
-                if response.next_page_token.is_none() {
-                    break;
+                // 複数のフォルダに属するファイルは最初に取得できた経路で記録する
+                if seen_files.insert(file.id.clone()) {
+                    file.folder_path = folder.folder_path.clone();
+                    crawl.files.push(file);
                 }
-                page_token = response.next_page_token;
             }
+
+            progress.send(SyncProgress::FolderDone {
+                folder_id: folder.folder_id,
+                completed_folders: crawl.folder_paths.len(),
+                remaining_folders: pending.len() + in_flight.len(),
+                total_files: crawl.files.len(),
+            });
         }
 
-        println!("直下ファイル取得完了: {}件のファイルを発見", all_files.len());
-        Ok(all_files)
+        info!("ファイル取得完了: {}個のフォルダから{}件のファイルを発見",
+            crawl.folder_paths.len(), crawl.files.len());
+        Ok(crawl)
+    }
+
+    // フォルダ直下のファイルを全ページ分取得する
+    async fn list_all_folder_contents(&self, folder_id: &str, progress: &ProgressSender) -> Result<Vec<DriveApiFile>> {
+        let mut files = Vec::new();
+        let mut page_token = None;
+        loop {
+            let response = self.list_folder_contents(folder_id, page_token).await?;
+            progress.send(SyncProgress::PageFetched {
+                folder_id: folder_id.to_string(),
+                page_items: response.files.len(),
+                folder_items: files.len() + response.files.len(),
+            });
+            files.extend(response.files);
+            if response.next_page_token.is_none() {
+                return Ok(files);
+            }
+            page_token = response.next_page_token;
+        }
     }
 
     async fn list_folder_contents(&self, folder_id: &str, page_token: Option<String>) -> Result<DriveFilesResponse> {
         let query = format!("'{}' in parents and trashed=false", folder_id);
         
         let mut params = vec![
-            ("fields", "files(id,name,webViewLink,modifiedTime,mimeType,parents),nextPageToken"),
+            ("fields", "files(id,name,webViewLink,modifiedTime,mimeType,parents,shortcutDetails(targetId,targetMimeType),owners(displayName,emailAddress)),nextPageToken"),
             ("pageSize", "1000"),
             ("q", query.as_str()),
             ("supportsAllDrives", "true"),
@@ -123,59 +298,73 @@ impl GoogleDriveClient {
             params.push(("pageToken", token));
         }
 
-        let response = self
-            .client
-            .get("https://www.googleapis.com/drive/v3/files")
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .query(&params)
-            .send()
+        self.http.get_json("https://www.googleapis.com/drive/v3/files", &params).await
+    }
+
+    pub async fn get_start_page_token(&self) -> Result<String> {
+        let token_response: StartPageTokenResponse = self.http
+            .get_json("https://www.googleapis.com/drive/v3/changes/startPageToken", &[("supportsAllDrives", "true")])
             .await?;
+        Ok(token_response.start_page_token)
+    }
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            anyhow::bail!("Drive API エラー: {}", error_text);
-        }
+    pub async fn list_changes(&self, page_token: &str) -> Result<DriveChangesResponse> {
+        let params = [
+            ("fields", "changes(fileId,removed,file(id,name,webViewLink,modifiedTime,mimeType,parents,trashed,shortcutDetails(targetId,targetMimeType),owners(displayName,emailAddress))),nextPageToken,newStartPageToken"),
+            ("pageSize", "1000"),
+            ("pageToken", page_token),
+            ("supportsAllDrives", "true"),
+            ("includeItemsFromAllDrives", "true"),
+            ("includeRemoved", "true"),
+        ];
 
-        let response_text = response.text().await?;
-        let files_response: DriveFilesResponse = serde_json::from_str(&response_text)?;
-        
-        Ok(files_response)
+        self.http.get_json("https://www.googleapis.com/drive/v3/changes", &params).await
     }
 
+    // 指定トークン以降の変更をすべて取得し、次回用のトークンと合わせて返す
+    pub async fn list_all_changes(&self, start_page_token: &str) -> Result<(Vec<DriveChange>, String)> {
+        let mut all_changes = Vec::new();
+        let mut page_token = start_page_token.to_string();
 
-    pub async fn test_connection(&self) -> Result<bool> {
-        let response = self
-            .client
-            .get("https://www.googleapis.com/drive/v3/about")
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .query(&[("fields", "user")])
-            .send()
-            .await?;
+        loop {
+            let response = self.list_changes(&page_token).await?;
+            all_changes.extend(response.changes);
+
+            if let Some(new_start_page_token) = response.new_start_page_token {
+                return Ok((all_changes, new_start_page_token));
+            }
 
-        Ok(response.status().is_success())
+            page_token = response.next_page_token
+                .ok_or_else(|| anyhow::anyhow!("変更一覧のページトークンが取得できませんでした"))?;
+        }
     }
-    
-    pub async fn get_folder_info(&self, folder_id: &str) -> Result<String> {
-        let response = self
-            .client
-            .get(&format!("https://www.googleapis.com/drive/v3/files/{}", folder_id))
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .query(&[
-                ("fields", "id,name,mimeType,webViewLink"),
-                ("supportsAllDrives", "true"),
-            ])
-            .send()
-            .await?;
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            println!("フォルダ情報取得エラー: {}", error_text);
-            anyhow::bail!("フォルダ情報の取得に失敗しました");
+    pub async fn test_connection(&self) -> Result<bool> {
+        // 拒否された（Drive API が無効など）場合は false、接続できない場合はエラー
+        match self.http.get_text("https://www.googleapis.com/drive/v3/about", &[("fields", "user")]).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.downcast_ref::<DriveApiError>().is_some() => Ok(false),
+            Err(e) => Err(e),
         }
+    }
+    
+    pub async fn get_folder_info(&self, folder_id: &str) -> Result<FolderInfo> {
+        let url = format!("https://www.googleapis.com/drive/v3/files/{}", folder_id);
+        self.http
+            .get_json(&url, &[("fields", "id,name,mimeType"), ("supportsAllDrives", "true")])
+            .await
+            .map_err(|e| e.context("フォルダ情報の取得に失敗しました"))
+    }
 
-        let folder_info = response.text().await?;
-        println!("フォルダ情報: {}", folder_info);
-        Ok(folder_info)
+    // 複数のフォルダの情報を requests の空きの数まで同時に取得し、指定した順に返す
+    pub async fn get_folder_infos(&self, folder_ids: &[String], requests: &Semaphore) -> Vec<(String, Result<FolderInfo>)> {
+        join_all(folder_ids.iter().map(|folder_id| async move {
+            let result = async {
+                let _permit = requests.acquire().await?;
+                self.get_folder_info(folder_id).await
+            }.await;
+            (folder_id.clone(), result)
+        }))
+        .await
     }
 }
-
@@ -0,0 +1,107 @@
+use anyhow::Result;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// 同じプロセス内で同時に書き込んでも一時ファイルが重ならないようにする連番
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// 一時ファイルに書き込んでから置き換えることで、読み込み側が書きかけのファイルを見ないようにする
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path.file_name()
+        .ok_or_else(|| anyhow::anyhow!("書き込み先のファイル名が不正です: {:?}", path))?
+        .to_string_lossy();
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let temp_path = dir.join(format!(
+        ".{}.{}.{}.tmp",
+        file_name,
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = (|| -> Result<()> {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    // 置き換え（rename）自体もディスクに反映させる
+    #[cfg(unix)]
+    if let Ok(dir_handle) = File::open(dir) {
+        let _ = dir_handle.sync_all();
+    }
+
+    Ok(())
+}
+
+// ロックファイルによるプロセス間の排他（保持している間だけ有効）
+// 同期処理の多重実行の防止や、履歴ファイルの読み書きの直列化に使う
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    // path のロックファイルを取得する。他が保持している場合は None を返す
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        let file = Self::open(path)?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    // path のロックファイルを、他が解放するまで待って取得する
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let file = Self::open(path)?;
+        file.lock()?;
+        Ok(Self { _file: file })
+    }
+
+    fn open(path: &Path) -> Result<File> {
+        Ok(OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn writes_atomically_from_concurrent_threads() {
+        let dir = temp_dir();
+        let path = dir.path().join("data.json");
+
+        let handles: Vec<_> = (0..8)
+            .map(|thread| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        write_atomic(&path, format!("thread-{}", thread).as_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // 書きかけや一時ファイルが残らない
+        assert!(fs::read_to_string(&path).unwrap().starts_with("thread-"));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
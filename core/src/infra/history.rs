@@ -0,0 +1,197 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::fs_util::{write_atomic, FileLock};
+use crate::error::Error;
+
+// 1ファイルあたりに残す開いた記録の数（古いものから捨てる）
+const MAX_EVENTS_PER_FILE: usize = 100;
+// 開いた記録の重みが半分になるまでの日数
+const HALF_LIFE_DAYS: f64 = 14.0;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryData {
+    // ファイルIDごとの開いた日時（古い順）
+    pub opens: HashMap<String, Vec<DateTime<Utc>>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenEvent {
+    pub file_id: String,
+    pub opened_at: DateTime<Utc>,
+}
+
+// ファイルを開いた履歴（history.json）
+pub struct HistoryStore {
+    path: PathBuf,
+    // 読み込みから保存までの間、他のプロセスの書き込みを待たせるためのロックファイル
+    lock_path: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn new(config_dir: &Path) -> Self {
+        Self {
+            path: config_dir.join("history.json"),
+            lock_path: config_dir.join("history.lock"),
+        }
+    }
+
+    pub fn load(&self) -> Result<HistoryData> {
+        match fs::read_to_string(&self.path) {
+            Ok(json_data) => serde_json::from_str(&json_data).map_err(|e| {
+                Error::StorageCorrupted(format!("開いた履歴を読み込めません（{}）: {}", self.path.display(), e)).into()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HistoryData::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, data: &HistoryData) -> Result<()> {
+        write_atomic(&self.path, serde_json::to_string(data)?.as_bytes())
+    }
+
+    // 同時に記録された開いた履歴が失われないよう、読み込みから保存までロックを保持する
+    fn lock(&self) -> Result<FileLock> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        FileLock::acquire(&self.lock_path)
+    }
+
+    pub fn record_open(&self, file_id: &str, opened_at: DateTime<Utc>) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut data = self.load()?;
+        let events = data.opens.entry(file_id.to_string()).or_default();
+        events.push(opened_at);
+        events.sort();
+        if events.len() > MAX_EVENTS_PER_FILE {
+            events.drain(..events.len() - MAX_EVENTS_PER_FILE);
+        }
+        self.save(&data)
+    }
+
+    // older_than より古い記録と、known_file_ids に含まれないファイルの記録を削除し、削除した件数を返す
+    pub fn prune(&self, older_than: Option<Duration>, known_file_ids: Option<&HashSet<String>>) -> Result<usize> {
+        let _lock = self.lock()?;
+        let mut data = self.load()?;
+        let cutoff = older_than.map(|age| Utc::now() - age);
+        let mut removed = 0;
+
+        data.opens.retain(|file_id, events| {
+            if known_file_ids.is_some_and(|ids| !ids.contains(file_id)) {
+                removed += events.len();
+                return false;
+            }
+            if let Some(cutoff) = cutoff {
+                let before = events.len();
+                events.retain(|opened_at| *opened_at >= cutoff);
+                removed += before - events.len();
+            }
+            !events.is_empty()
+        });
+
+        if removed > 0 {
+            self.save(&data)?;
+        }
+        Ok(removed)
+    }
+
+    // すべての記録を日時順に返す
+    pub fn export(&self) -> Result<Vec<OpenEvent>> {
+        let data = self.load()?;
+        let mut events: Vec<OpenEvent> = data.opens.into_iter()
+            .flat_map(|(file_id, opens)| {
+                opens.into_iter().map(move |opened_at| OpenEvent {
+                    file_id: file_id.clone(),
+                    opened_at,
+                })
+            })
+            .collect();
+        events.sort_by(|a, b| a.opened_at.cmp(&b.opened_at).then_with(|| a.file_id.cmp(&b.file_id)));
+        Ok(events)
+    }
+}
+
+impl HistoryData {
+    // 開いた回数と新しさを合わせた値（1回開くごとに最大1、日数の経過で半減していく）
+    pub fn frecency(&self, file_id: &str, now: DateTime<Utc>) -> f64 {
+        self.opens.get(file_id)
+            .map(|events| {
+                events.iter()
+                    .map(|opened_at| {
+                        let age_days = (now - *opened_at).num_seconds().max(0) as f64 / 86_400.0;
+                        0.5f64.powf(age_days / HALF_LIFE_DAYS)
+                    })
+                    .sum()
+            })
+            .unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn decays_with_age_and_grows_with_frequency() {
+        let now = Utc::now();
+        let mut data = HistoryData::default();
+        data.opens.insert("recent".to_string(), vec![now]);
+        data.opens.insert("old".to_string(), vec![now - Duration::days(14)]);
+        data.opens.insert("frequent".to_string(), vec![now - Duration::days(14); 5]);
+
+        assert!((data.frecency("recent", now) - 1.0).abs() < 1e-9);
+        assert!((data.frecency("old", now) - 0.5).abs() < 1e-9);
+        assert!(data.frecency("frequent", now) > data.frecency("recent", now));
+        assert_eq!(data.frecency("unknown", now), 0.0);
+    }
+
+    #[test]
+    fn records_prunes_and_exports() {
+        let dir = temp_dir();
+        let store = HistoryStore::new(dir.path());
+        let now = Utc::now();
+        store.record_open("a", now - Duration::days(200)).unwrap();
+        store.record_open("a", now).unwrap();
+        store.record_open("b", now).unwrap();
+        store.record_open("gone", now).unwrap();
+
+        assert_eq!(store.prune(Some(Duration::days(180)), None).unwrap(), 1);
+
+        let known: HashSet<String> = ["a", "b"].iter().map(|id| id.to_string()).collect();
+        assert_eq!(store.prune(None, Some(&known)).unwrap(), 1);
+
+        let ids: Vec<String> = store.export().unwrap().into_iter().map(|event| event.file_id).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn keeps_concurrent_records() {
+        let temp = temp_dir();
+        let dir = temp.path().to_path_buf();
+        let now = Utc::now();
+
+        let handles: Vec<_> = (0..8)
+            .map(|thread| {
+                let dir = dir.clone();
+                std::thread::spawn(move || {
+                    // プロセスごとに別のインスタンスを使うのと同じく、ロックファイルを別々に開く
+                    let store = HistoryStore::new(&dir);
+                    for i in 0..10 {
+                        store.record_open(&format!("file-{}", thread), now + Duration::seconds(i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(HistoryStore::new(&dir).export().unwrap().len(), 80);
+    }
+}
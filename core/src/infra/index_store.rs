@@ -0,0 +1,79 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::config::{FolderSettings, StorageBackend};
+use super::storage::{JsonStorage, JsonStorageData, JsonStorageFile};
+use crate::models::DriveFile;
+
+// 同期したファイル一覧の保存先
+// 各メソッドの既定実装は load_data を使うので、バックエンドは必要に応じて効率の良い実装で上書きする
+pub trait IndexStore: Send + Sync {
+    fn save_data(
+        &mut self,
+        files: &[DriveFile],
+        folder_names: &HashMap<String, String>,
+        folder_paths: &HashMap<String, Vec<String>>,
+        folder_settings: &HashMap<String, FolderSettings>,
+        sync_token: Option<String>,
+    ) -> Result<()>;
+
+    fn load_data(&self) -> Result<Option<JsonStorageData>>;
+
+    fn get_files(&self) -> Result<Vec<DriveFile>> {
+        if let Some(data) = self.load_data()? {
+            Ok(data.files.into_iter().map(JsonStorageFile::into_drive_file).collect())
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn get_file_count(&self) -> Result<usize> {
+        if let Some(data) = self.load_data()? {
+            Ok(data.files.len())
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn get_sync_info(&self) -> Result<Option<(DateTime<Utc>, Option<String>)>> {
+        if let Some(data) = self.load_data()? {
+            Ok(Some((data.last_sync, data.sync_token)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn get_folder_names(&self) -> Result<HashMap<String, String>> {
+        if let Some(data) = self.load_data()? {
+            Ok(data.folders)
+        } else {
+            Ok(HashMap::new())
+        }
+    }
+
+    // 検索語に一致しうるファイルを返す（絞り込めないバックエンドは全件を返す）
+    fn search_candidates(&self, _words: &[&str]) -> Result<Vec<JsonStorageFile>> {
+        if let Some(data) = self.load_data()? {
+            Ok(data.files)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+}
+
+// 設定されたバックエンドのストレージを開く
+pub fn open_index_store(config_dir: &Path, backend: StorageBackend) -> Result<Box<dyn IndexStore>> {
+    match backend {
+        StorageBackend::Json => Ok(Box::new(JsonStorage::new(config_dir.join("drive_files.json"))?)),
+        #[cfg(feature = "sqlite")]
+        StorageBackend::Sqlite => Ok(Box::new(super::sqlite_store::SqliteStore::new(
+            config_dir.join("drive_files.sqlite3"),
+        )?)),
+        #[cfg(not(feature = "sqlite"))]
+        StorageBackend::Sqlite => Err(anyhow::anyhow!(
+            "SQLiteストレージを使うには sqlite 機能を有効にしてビルドしてください"
+        )),
+    }
+}
@@ -1,9 +1,25 @@
 pub mod auth;
+pub mod auth_client;
 pub mod config;
 pub mod drive;
+pub mod fs_util;
+pub mod history;
+pub mod index_store;
+pub mod ngram_index;
+pub mod retry;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
 pub mod storage;
 
 pub use auth::*;
+pub use auth_client::*;
 pub use config::*;
 pub use drive::*;
+pub use fs_util::*;
+pub use history::*;
+pub use index_store::*;
+pub use ngram_index::*;
+pub use retry::*;
+#[cfg(feature = "sqlite")]
+pub use sqlite_store::*;
 pub use storage::*;
\ No newline at end of file
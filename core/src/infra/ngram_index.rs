@@ -0,0 +1,367 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use super::fs_util::write_atomic;
+use super::storage::{JsonStorageData, JsonStorageFile};
+use crate::text::normalize::normalize;
+use crate::text::romaji::canonical_romaji;
+
+// 検索候補を絞り込むための n-gram 転置インデックス
+// 英数字などは3文字、かな・漢字は2文字単位で、名前・キーワード・ローマ字・フォルダ経路を索引する
+// 部分列での一致（"prjct" → "Project" など）のために、含まれる文字も1文字単位で索引する
+// drive_files.json の隣に独自のバイナリ形式で保存する
+
+const MAGIC: &[u8; 8] = b"FDSNGRAM";
+// 索引の作り方やファイル形式を変えたら上げる（古い索引は読み込み時に作り直される）
+pub const NGRAM_INDEX_VERSION: u32 = 2;
+
+const LATIN_GRAM_CHARS: usize = 3;
+const CJK_GRAM_CHARS: usize = 2;
+
+pub struct NgramIndex {
+    // 索引の元になったデータの同期日時（ミリ秒）とファイル数。一致しなければ作り直す
+    source_sync_millis: i64,
+    file_count: u32,
+    // n-gram ごとの、それを含むファイルの位置（昇順）
+    postings: HashMap<String, Vec<u32>>,
+}
+
+impl NgramIndex {
+    pub fn build(data: &JsonStorageData) -> Self {
+        let mut postings: HashMap<String, Vec<u32>> = HashMap::new();
+
+        for (position, file) in data.files.iter().enumerate() {
+            for gram in file_grams(file) {
+                postings.entry(gram).or_default().push(position as u32);
+            }
+        }
+
+        Self {
+            source_sync_millis: data.last_sync.timestamp_millis(),
+            file_count: data.files.len() as u32,
+            postings,
+        }
+    }
+
+    pub fn is_current(&self, data: &JsonStorageData) -> bool {
+        self.source_sync_millis == data.last_sync.timestamp_millis() && self.file_count as usize == data.files.len()
+    }
+
+    // 検索語（正規化済み）すべてに一致しうるファイルの位置を昇順で返す
+    // 索引で絞り込めない場合（短い語のみなど）は None
+    pub fn candidates(&self, words: &[&str]) -> Option<Vec<usize>> {
+        let mut result: Option<Vec<bool>> = None;
+
+        for word in words {
+            let Some(mut matched) = self.word_candidates(word, allowed_missing_grams(word)) else {
+                continue;
+            };
+
+            // ローマ字の語は、表記ゆれを吸収した形でも一致させる（こちらは綴り間違いを許容しない）
+            if word.chars().any(|c| c.is_ascii_alphabetic()) {
+                let canonical = canonical_romaji(word);
+                if canonical != *word {
+                    // 表記ゆれを吸収した形で絞り込めない語は、絞り込まずに全件を残す
+                    let Some(canonical_matched) = self.word_candidates(&canonical, 0) else {
+                        continue;
+                    };
+                    for (m, c) in matched.iter_mut().zip(canonical_matched) {
+                        *m |= c;
+                    }
+                }
+            }
+
+            result = Some(match result {
+                Some(mut current) => {
+                    for (c, m) in current.iter_mut().zip(matched) {
+                        *c &= m;
+                    }
+                    current
+                }
+                None => matched,
+            });
+        }
+
+        result.map(|matched| {
+            matched.iter()
+                .enumerate()
+                .filter(|(_, &m)| m)
+                .map(|(position, _)| position)
+                .collect()
+        })
+    }
+
+    // 綴り間違いを許容するため、語の n-gram のうち一定数以上を含むファイルを候補にする
+    fn word_candidates(&self, word: &str, allowed_missing: usize) -> Option<Vec<bool>> {
+        let grams = text_grams(word, false);
+        if grams.is_empty() {
+            return None;
+        }
+
+        let required = grams.len().saturating_sub(allowed_missing).max(1);
+        let mut counts = vec![0u16; self.file_count as usize];
+        for gram in &grams {
+            if let Some(positions) = self.postings.get(gram) {
+                for &position in positions {
+                    counts[position as usize] = counts[position as usize].saturating_add(1);
+                }
+            }
+        }
+
+        // 部分列として一致しうる（語の文字をすべて含む）ファイルも残す
+        let subsequence = self.char_candidates(word);
+        Some(counts.into_iter()
+            .zip(subsequence)
+            .map(|(count, contains_chars)| count as usize >= required || contains_chars)
+            .collect())
+    }
+
+    // 語に含まれる文字（空白以外）をすべて含むファイル
+    fn char_candidates(&self, word: &str) -> Vec<bool> {
+        let mut matched = vec![true; self.file_count as usize];
+        let chars: HashSet<char> = word.chars().filter(|c| !c.is_whitespace()).collect();
+        for c in chars {
+            let mut contains = vec![false; self.file_count as usize];
+            for &position in self.postings.get(&c.to_string()).into_iter().flatten() {
+                contains[position as usize] = true;
+            }
+            for (m, c) in matched.iter_mut().zip(contains) {
+                *m &= c;
+            }
+        }
+        matched
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &self.encode())
+    }
+
+    // 存在しない、または形式が古い・壊れている場合は None
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self::decode(&bytes))
+    }
+
+    // 形式: MAGIC, バージョン(u32), 同期日時(i64), ファイル数(u32), n-gram数(u32),
+    //       以降 n-gram ごとに [長さ(varint), UTF-8, 件数(varint), 位置の差分(varint)...]
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&NGRAM_INDEX_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.source_sync_millis.to_le_bytes());
+        out.extend_from_slice(&self.file_count.to_le_bytes());
+        out.extend_from_slice(&(self.postings.len() as u32).to_le_bytes());
+
+        for (gram, positions) in &self.postings {
+            write_varint(&mut out, gram.len() as u64);
+            out.extend_from_slice(gram.as_bytes());
+            write_varint(&mut out, positions.len() as u64);
+            let mut previous = 0;
+            for &position in positions {
+                write_varint(&mut out, (position - previous) as u64);
+                previous = position;
+            }
+        }
+
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = ByteReader { bytes, pos: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return None;
+        }
+        if u32::from_le_bytes(reader.take(4)?.try_into().ok()?) != NGRAM_INDEX_VERSION {
+            return None;
+        }
+        let source_sync_millis = i64::from_le_bytes(reader.take(8)?.try_into().ok()?);
+        let file_count = u32::from_le_bytes(reader.take(4)?.try_into().ok()?);
+        let gram_count = u32::from_le_bytes(reader.take(4)?.try_into().ok()?);
+
+        let mut postings = HashMap::with_capacity(gram_count as usize);
+        for _ in 0..gram_count {
+            let gram_len = reader.varint()? as usize;
+            let gram = std::str::from_utf8(reader.take(gram_len)?).ok()?.to_string();
+            let count = reader.varint()? as usize;
+            let mut positions = Vec::with_capacity(count);
+            let mut position = 0u32;
+            for _ in 0..count {
+                position = position.checked_add(u32::try_from(reader.varint()?).ok()?)?;
+                if position >= file_count {
+                    return None;
+                }
+                positions.push(position);
+            }
+            postings.insert(gram, positions);
+        }
+
+        if reader.pos != bytes.len() {
+            return None;
+        }
+
+        Some(Self {
+            source_sync_millis,
+            file_count,
+            postings,
+        })
+    }
+}
+
+// matcher の綴り間違いの許容（4文字以上で1文字、8文字以上で2文字）に合わせ、
+// 1文字の間違いで失われうる n-gram の数だけ不一致を許す
+fn allowed_missing_grams(word: &str) -> usize {
+    let len = word.chars().count();
+    let typos = match len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    typos * LATIN_GRAM_CHARS
+}
+
+fn file_grams(file: &JsonStorageFile) -> HashSet<String> {
+    let normalized_name = if file.normalized_name.is_empty() {
+        normalize(&file.name)
+    } else {
+        file.normalized_name.clone()
+    };
+
+    let mut grams = text_grams(&normalized_name, true);
+    for text in file.keywords.iter().chain(&file.romaji_keywords).chain(&file.romaji_canonical) {
+        grams.extend(text_grams(text, true));
+    }
+    grams.extend(text_grams(&normalize(&file.folder_breadcrumb), true));
+    grams
+}
+
+// かな・漢字（CJK）とそれ以外で文字種の連続に分け、それぞれの単位で n-gram を作る
+// index が真なら含まれる文字も1文字単位で加える。検索語では、かな・漢字の1文字単位は2文字以上の連続が無いときだけ使う
+fn text_grams(text: &str, index: bool) -> HashSet<String> {
+    let mut grams = HashSet::new();
+
+    for segment in text.split_whitespace() {
+        let chars: Vec<char> = segment.chars().collect();
+        let mut start = 0;
+        while start < chars.len() {
+            let cjk = is_cjk(chars[start]);
+            let mut end = start + 1;
+            while end < chars.len() && is_cjk(chars[end]) == cjk {
+                end += 1;
+            }
+
+            let run = &chars[start..end];
+            let size = if cjk { CJK_GRAM_CHARS } else { LATIN_GRAM_CHARS };
+            if run.len() >= size {
+                grams.extend(run.windows(size).map(|window| window.iter().collect::<String>()));
+            }
+            // 1文字の漢字・かなでも検索できるよう、1文字単位も含める
+            if index || (cjk && run.len() < size) {
+                grams.extend(run.iter().map(|c| c.to_string()));
+            }
+
+            start = end;
+        }
+    }
+
+    grams
+}
+
+fn is_cjk(c: char) -> bool {
+    c as u32 >= 0x2E80
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let slice = self.bytes.get(self.pos..end)?;
+        self.pos = end;
+        Some(slice)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.take(1)?.first()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::storage::build_storage_files;
+    use crate::test_util::sample_files;
+    use chrono::Utc;
+
+    fn sample_data() -> JsonStorageData {
+        JsonStorageData {
+            files: build_storage_files(&sample_files(), &HashMap::new()),
+            folders: HashMap::new(),
+            folder_paths: HashMap::new(),
+            folder_settings: HashMap::new(),
+            last_sync: Utc::now(),
+            sync_token: None,
+        }
+    }
+
+    #[test]
+    fn narrows_candidates_by_ngrams() {
+        let index = NgramIndex::build(&sample_data());
+
+        assert_eq!(index.candidates(&["proposal"]), Some(vec![0]));
+        assert_eq!(index.candidates(&["議事"]), Some(vec![1]));
+        assert_eq!(index.candidates(&["議"]), Some(vec![1]));
+        // 綴り間違いやローマ字の表記ゆれでも候補に残す
+        assert_eq!(index.candidates(&["prposal"]), Some(vec![0]));
+        assert_eq!(index.candidates(&["siryou"]), Some(vec![2]));
+        // 部分列として一致する語も候補に残す
+        assert_eq!(index.candidates(&["prjct"]), Some(vec![0]));
+        assert_eq!(index.candidates(&["議録"]), Some(vec![1]));
+        // AND 検索
+        assert_eq!(index.candidates(&["proposal", "議事"]), Some(vec![]));
+        // 短い英字の語では絞り込めない
+        assert_eq!(index.candidates(&["pp"]), None);
+    }
+
+    #[test]
+    fn round_trips_binary_format() {
+        let data = sample_data();
+        let index = NgramIndex::build(&data);
+        let decoded = NgramIndex::decode(&index.encode()).unwrap();
+
+        assert!(decoded.is_current(&data));
+        assert_eq!(decoded.postings, index.postings);
+
+        let mut corrupted = index.encode();
+        corrupted.truncate(corrupted.len() - 1);
+        assert!(NgramIndex::decode(&corrupted).is_none());
+
+        let mut old_version = index.encode();
+        old_version[MAGIC.len()] = 0;
+        assert!(NgramIndex::decode(&old_version).is_none());
+    }
+}
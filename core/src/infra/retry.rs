@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::time::Duration;
+
+// Google API のエラー応答の分類と、一時的な失敗を再試行するときの待ち時間の計算
+// https://developers.google.com/drive/api/guides/handle-errors
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    // 最初の試行を含む最大試行回数
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // Retry-After で指示された待ち時間の上限（これより長ければ待たずに失敗とする）
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(32),
+            max_retry_after: Duration::from_secs(120),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorClass {
+    // 429 や 403 userRateLimitExceeded など、待てば成功しうるもの
+    RateLimited,
+    // 5xx やタイムアウトなど、一時的な障害
+    Transient,
+    // 権限不足や存在しないファイルなど、再試行しても変わらないもの
+    Permanent,
+}
+
+impl ErrorClass {
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorClass::RateLimited | ErrorClass::Transient)
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Drive API エラー（{status}{}）: {message}", reason.as_ref().map(|reason| format!(" {}", reason)).unwrap_or_default())]
+pub struct DriveApiError {
+    pub status: StatusCode,
+    // errors[0].reason（userRateLimitExceeded など）
+    pub reason: Option<String>,
+    pub message: String,
+    pub class: ErrorClass,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    errors: Vec<ErrorDetail>,
+}
+
+#[derive(Deserialize)]
+struct ErrorDetail {
+    #[serde(default)]
+    reason: String,
+}
+
+// 403 でも再試行すべき理由（日ごとの上限 dailyLimitExceeded は待っても回復しない）
+const RATE_LIMIT_REASONS: [&str; 3] = ["userRateLimitExceeded", "rateLimitExceeded", "sharingRateLimitExceeded"];
+
+impl DriveApiError {
+    // エラー応答の本文（{"error": {"message": ..., "errors": [{"reason": ...}]}}）から分類する
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        let (message, reason) = match serde_json::from_str::<ErrorResponse>(body) {
+            Ok(response) => (
+                response.error.message,
+                response.error.errors.into_iter().next().map(|detail| detail.reason).filter(|reason| !reason.is_empty()),
+            ),
+            Err(_) => (body.chars().take(200).collect(), None),
+        };
+
+        let class = match status.as_u16() {
+            429 => ErrorClass::RateLimited,
+            403 if reason.as_deref().is_some_and(|reason| RATE_LIMIT_REASONS.contains(&reason)) => ErrorClass::RateLimited,
+            408 | 500 | 502 | 503 | 504 => ErrorClass::Transient,
+            _ => ErrorClass::Permanent,
+        };
+
+        Self {
+            status,
+            reason,
+            message,
+            class,
+        }
+    }
+}
+
+// Retry-After（秒数または HTTP 日付）を待ち時間にする
+pub fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+    Some((date - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+impl RetryPolicy {
+    // attempt 回目（1始まり）の失敗の後に待つ時間
+    // 指数的に伸ばした上限の半分から上限までの間でばらつかせ、同時に再試行が集中しないようにする
+    // Retry-After があればその時間だけ待ち、max_retry_after を超える場合は再試行しない（None）
+    // jitter は 0.0〜1.0 の乱数
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>, jitter: f64) -> Option<Duration> {
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= self.max_retry_after).then_some(retry_after);
+        }
+
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exponential.min(self.max_delay);
+        Some(capped.mul_f64(0.5 + 0.5 * jitter.clamp(0.0, 1.0)))
+    }
+}
+
+pub fn random_jitter() -> f64 {
+    uuid::Uuid::new_v4().as_u128() as f64 / u128::MAX as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn classifies_drive_error_bodies() {
+        let body = r#"{"error":{"code":403,"message":"User Rate Limit Exceeded","errors":[{"domain":"usageLimits","reason":"userRateLimitExceeded"}]}}"#;
+        let error = DriveApiError::from_response(StatusCode::FORBIDDEN, body);
+        assert_eq!(error.class, ErrorClass::RateLimited);
+        assert_eq!(error.reason.as_deref(), Some("userRateLimitExceeded"));
+
+        let body = r#"{"error":{"code":403,"message":"The user does not have sufficient permissions","errors":[{"reason":"insufficientFilePermissions"}]}}"#;
+        assert_eq!(DriveApiError::from_response(StatusCode::FORBIDDEN, body).class, ErrorClass::Permanent);
+
+        assert_eq!(DriveApiError::from_response(StatusCode::TOO_MANY_REQUESTS, "").class, ErrorClass::RateLimited);
+        let error = DriveApiError::from_response(StatusCode::BAD_GATEWAY, "<html>Bad Gateway</html>");
+        assert_eq!(error.class, ErrorClass::Transient);
+        assert_eq!(error.message, "<html>Bad Gateway</html>");
+        assert_eq!(DriveApiError::from_response(StatusCode::NOT_FOUND, "{}").class, ErrorClass::Permanent);
+    }
+
+    #[test]
+    fn backs_off_exponentially_with_jitter_and_retry_after() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, None, 1.0), Some(Duration::from_millis(500)));
+        assert_eq!(policy.delay(1, None, 0.0), Some(Duration::from_millis(250)));
+        assert_eq!(policy.delay(3, None, 1.0), Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(20, None, 1.0), Some(Duration::from_secs(32)));
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(7)), 0.0), Some(Duration::from_secs(7)));
+        // Retry-After は max_delay より長くても指示どおり待ち、上限を超えれば再試行しない
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(90)), 0.0), Some(Duration::from_secs(90)));
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(600)), 0.0), None);
+
+        let now = Utc::now();
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("12"));
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(12)));
+
+        let date = (now + chrono::Duration::seconds(30)).to_rfc2822();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(&date).unwrap());
+        let delay = retry_after(&headers, now).unwrap();
+        assert!(delay <= Duration::from_secs(30) && delay >= Duration::from_secs(29));
+    }
+}
@@ -0,0 +1,390 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Params, Row};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::debug;
+
+use super::config::FolderSettings;
+use super::index_store::IndexStore;
+use super::storage::{build_storage_files, JsonStorageData, JsonStorageFile};
+use crate::models::DriveFile;
+use crate::text::normalize::normalize;
+use crate::text::romaji::canonical_romaji;
+
+const SCHEMA_VERSION: i64 = 4;
+
+const FILE_COLUMNS: &str = "f.id, f.name, f.normalized_name, f.web_view_link, f.modified_time, f.mime_type, f.parents, \
+     f.parent_folder_name, f.folder_path, f.folder_breadcrumb, f.keywords, f.romaji_keywords, f.romaji_canonical, \
+     f.owners";
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn new(storage_path: PathBuf) -> Result<Self> {
+        if let Some(parent) = storage_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(&storage_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version != SCHEMA_VERSION {
+            // スキーマが古い場合は作り直し、次回の同期で全件を取得する
+            conn.execute_batch(
+                "DROP TABLE IF EXISTS files_fts;
+                 DROP TABLE IF EXISTS files;
+                 DROP TABLE IF EXISTS folders;
+                 DROP TABLE IF EXISTS meta;",
+            )?;
+        }
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS meta (
+                 key TEXT PRIMARY KEY,
+                 value TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS folders (
+                 id TEXT PRIMARY KEY,
+                 name TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS files (
+                 rowid INTEGER PRIMARY KEY,
+                 id TEXT NOT NULL UNIQUE,
+                 name TEXT NOT NULL,
+                 normalized_name TEXT NOT NULL,
+                 web_view_link TEXT NOT NULL,
+                 modified_time TEXT NOT NULL,
+                 mime_type TEXT NOT NULL,
+                 parents TEXT NOT NULL,
+                 parent_folder_name TEXT NOT NULL,
+                 folder_path TEXT NOT NULL,
+                 folder_breadcrumb TEXT NOT NULL,
+                 keywords TEXT NOT NULL,
+                 romaji_keywords TEXT NOT NULL,
+                 romaji_canonical TEXT NOT NULL,
+                 owners TEXT NOT NULL
+             );
+             CREATE VIRTUAL TABLE IF NOT EXISTS files_fts USING fts5(
+                 name, keywords, romaji, folder,
+                 tokenize = 'trigram'
+             );",
+        )?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn.lock().map_err(|_| anyhow::anyhow!("SQLiteの接続が破損しています"))
+    }
+
+    fn get_meta<T: DeserializeOwned>(conn: &Connection, key: &str) -> Result<Option<T>> {
+        let value: Option<String> = conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()?;
+        match value {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn query_files<P: Params>(conn: &Connection, sql: &str, params: P) -> Result<Vec<JsonStorageFile>> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params, row_to_file)?;
+
+        let mut files = Vec::new();
+        for row in rows {
+            files.push(row?);
+        }
+        Ok(files)
+    }
+
+    // 語の trigram のうち、許容する不一致の数を除いた数以上を含む行
+    fn trigram_candidates(conn: &Connection, word: &str, allowed_missing: usize) -> Result<HashSet<i64>> {
+        let grams = trigrams(word);
+        let required = grams.len().saturating_sub(allowed_missing).max(1);
+
+        let mut stmt = conn.prepare_cached("SELECT rowid FROM files_fts WHERE files_fts MATCH ?1")?;
+        let mut counts: HashMap<i64, usize> = HashMap::new();
+        for gram in &grams {
+            for rowid in stmt.query_map([fts_phrase(gram)], |row| row.get::<_, i64>(0))? {
+                *counts.entry(rowid?).or_default() += 1;
+            }
+        }
+        Ok(counts.into_iter().filter(|&(_, count)| count >= required).map(|(rowid, _)| rowid).collect())
+    }
+
+    fn folder_names(conn: &Connection) -> Result<HashMap<String, String>> {
+        let mut stmt = conn.prepare("SELECT id, name FROM folders")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+        let mut folder_names = HashMap::new();
+        for row in rows {
+            let (folder_id, name) = row?;
+            folder_names.insert(folder_id, name);
+        }
+        Ok(folder_names)
+    }
+
+    fn all_files(conn: &Connection) -> Result<Vec<JsonStorageFile>> {
+        Self::query_files(conn, &format!("SELECT {} FROM files f ORDER BY f.rowid", FILE_COLUMNS), [])
+    }
+}
+
+impl IndexStore for SqliteStore {
+    fn save_data(
+        &mut self,
+        files: &[DriveFile],
+        folder_names: &HashMap<String, String>,
+        folder_paths: &HashMap<String, Vec<String>>,
+        folder_settings: &HashMap<String, FolderSettings>,
+        sync_token: Option<String>,
+    ) -> Result<()> {
+        let storage_files = build_storage_files(files, folder_names);
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+
+        tx.execute_batch("DELETE FROM files; DELETE FROM files_fts; DELETE FROM folders;")?;
+
+        {
+            let mut insert_file = tx.prepare(
+                "INSERT INTO files (id, name, normalized_name, web_view_link, modified_time, mime_type,
+                     parents, parent_folder_name, folder_path, folder_breadcrumb, keywords, romaji_keywords,
+                     romaji_canonical, owners)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?;
+            let mut insert_fts = tx.prepare(
+                "INSERT INTO files_fts (rowid, name, keywords, romaji, folder) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+
+            for file in &storage_files {
+                insert_file.execute(params![
+                    file.id,
+                    file.name,
+                    file.normalized_name,
+                    file.web_view_link,
+                    file.modified_time,
+                    file.mime_type,
+                    serde_json::to_string(&file.parents)?,
+                    file.parent_folder_name,
+                    serde_json::to_string(&file.folder_path)?,
+                    file.folder_breadcrumb,
+                    serde_json::to_string(&file.keywords)?,
+                    serde_json::to_string(&file.romaji_keywords)?,
+                    serde_json::to_string(&file.romaji_canonical)?,
+                    serde_json::to_string(&file.owners)?,
+                ])?;
+                insert_fts.execute(params![
+                    tx.last_insert_rowid(),
+                    file.normalized_name,
+                    file.keywords.join("\n"),
+                    format!("{} {}", file.romaji_keywords.join(" "), file.romaji_canonical.join(" ")),
+                    normalize(&file.folder_breadcrumb),
+                ])?;
+            }
+
+            let mut insert_folder = tx.prepare("INSERT INTO folders (id, name) VALUES (?1, ?2)")?;
+            for (folder_id, name) in folder_names {
+                insert_folder.execute(params![folder_id, name])?;
+            }
+
+            let mut upsert_meta = tx.prepare("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)")?;
+            upsert_meta.execute(params!["last_sync", serde_json::to_string(&Utc::now())?])?;
+            upsert_meta.execute(params!["sync_token", serde_json::to_string(&sync_token)?])?;
+            upsert_meta.execute(params!["folder_paths", serde_json::to_string(folder_paths)?])?;
+            upsert_meta.execute(params!["folder_settings", serde_json::to_string(folder_settings)?])?;
+        }
+
+        tx.commit()?;
+
+        debug!("SQLiteストレージに{}件のファイルを保存しました", files.len());
+        Ok(())
+    }
+
+    fn load_data(&self) -> Result<Option<JsonStorageData>> {
+        let conn = self.lock()?;
+        let Some(last_sync) = Self::get_meta::<DateTime<Utc>>(&conn, "last_sync")? else {
+            return Ok(None);
+        };
+
+        Ok(Some(JsonStorageData {
+            files: Self::all_files(&conn)?,
+            folders: Self::folder_names(&conn)?,
+            folder_paths: Self::get_meta(&conn, "folder_paths")?.unwrap_or_default(),
+            folder_settings: Self::get_meta(&conn, "folder_settings")?.unwrap_or_default(),
+            last_sync,
+            sync_token: Self::get_meta::<Option<String>>(&conn, "sync_token")?.flatten(),
+        }))
+    }
+
+    fn get_file_count(&self) -> Result<usize> {
+        let conn = self.lock()?;
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    fn get_sync_info(&self) -> Result<Option<(DateTime<Utc>, Option<String>)>> {
+        let conn = self.lock()?;
+        let Some(last_sync) = Self::get_meta::<DateTime<Utc>>(&conn, "last_sync")? else {
+            return Ok(None);
+        };
+        let sync_token = Self::get_meta::<Option<String>>(&conn, "sync_token")?.flatten();
+        Ok(Some((last_sync, sync_token)))
+    }
+
+    fn get_folder_names(&self) -> Result<HashMap<String, String>> {
+        let conn = self.lock()?;
+        Self::folder_names(&conn)
+    }
+
+    fn search_candidates(&self, words: &[&str]) -> Result<Vec<JsonStorageFile>> {
+        let conn = self.lock()?;
+
+        // FTS の trigram 索引で語ごとに候補を絞り、綴り間違い・部分列の判定は候補に対する採点に任せる。
+        // JSON の n-gram 索引と同じく、1文字の間違いで失われうる trigram の数だけ不一致を許す
+        let mut result: Option<HashSet<i64>> = None;
+        for word in words {
+            let mut forms = vec![(word.to_string(), allowed_missing_trigrams(word))];
+            if word.chars().any(|c| c.is_ascii_alphabetic()) {
+                // 表記ゆれを吸収した形でも一致させる（こちらは綴り間違いを許容しない）
+                let canonical = canonical_romaji(word);
+                if canonical != *word {
+                    forms.push((canonical, 0));
+                }
+            }
+            // trigram トークナイザは3文字未満の語を検索できないので、絞り込みに使わない
+            if forms.iter().any(|(form, _)| trigrams(form).is_empty()) {
+                continue;
+            }
+
+            let mut matched = HashSet::new();
+            for (form, allowed_missing) in &forms {
+                matched.extend(Self::trigram_candidates(&conn, form, *allowed_missing)?);
+            }
+            result = Some(match result {
+                Some(current) => current.intersection(&matched).copied().collect(),
+                None => matched,
+            });
+        }
+        let Some(rowids) = result else {
+            return Self::all_files(&conn);
+        };
+
+        let sql = format!(
+            "SELECT {} FROM files f WHERE f.rowid IN (SELECT value FROM json_each(?1)) ORDER BY f.rowid",
+            FILE_COLUMNS
+        );
+        let rowids: Vec<i64> = rowids.into_iter().collect();
+        Self::query_files(&conn, &sql, [serde_json::to_string(&rowids)?])
+    }
+}
+
+// matcher の綴り間違いの許容（4文字以上で1文字、8文字以上で2文字）に合わせる
+fn allowed_missing_trigrams(word: &str) -> usize {
+    let typos = match word.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    typos * 3
+}
+
+// 語の3文字単位（trigram トークナイザの単位に合わせる）
+fn trigrams(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut grams: Vec<String> = chars.windows(3).map(|window| window.iter().collect()).collect();
+    grams.sort();
+    grams.dedup();
+    grams
+}
+
+fn fts_phrase(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+fn json_column<T: DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(index)?;
+    serde_json::from_str(&text)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+fn row_to_file(row: &Row) -> rusqlite::Result<JsonStorageFile> {
+    Ok(JsonStorageFile {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        normalized_name: row.get(2)?,
+        web_view_link: row.get(3)?,
+        modified_time: row.get(4)?,
+        mime_type: row.get(5)?,
+        parents: json_column(row, 6)?,
+        parent_folder_name: row.get(7)?,
+        folder_path: json_column(row, 8)?,
+        folder_breadcrumb: row.get(9)?,
+        keywords: json_column(row, 10)?,
+        romaji_keywords: json_column(row, 11)?,
+        romaji_canonical: json_column(row, 12)?,
+        owners: json_column(row, 13)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{sample_files, temp_dir};
+
+    fn candidate_ids(store: &SqliteStore, words: &[&str]) -> Vec<String> {
+        store.search_candidates(words).unwrap().into_iter().map(|file| file.id).collect()
+    }
+
+    #[test]
+    fn saves_loads_and_searches_candidates() {
+        let dir = temp_dir();
+        let path = dir.path().join("drive_files.db");
+        let mut store = SqliteStore::new(path.clone()).unwrap();
+        let folder_paths = HashMap::from([("folder".to_string(), vec!["root".to_string()])]);
+        store.save_data(&sample_files(), &HashMap::new(), &folder_paths, &HashMap::new(), Some("token".to_string())).unwrap();
+
+        let data = store.load_data().unwrap().unwrap();
+        assert_eq!(data.files.len(), 4);
+        assert_eq!(data.files[1].name, "営業会議_議事録");
+        assert_eq!(data.folder_paths, folder_paths);
+        assert_eq!(data.sync_token.as_deref(), Some("token"));
+
+        assert_eq!(candidate_ids(&store, &["proposal"]), vec!["0"]);
+        // 綴り間違い・ローマ字の表記ゆれで一致するものも候補に残す
+        assert!(candidate_ids(&store, &["prposal"]).contains(&"0".to_string()));
+        assert!(candidate_ids(&store, &["proposl"]).contains(&"0".to_string()));
+        assert!(candidate_ids(&store, &["siryou"]).contains(&"2".to_string()));
+        assert!(candidate_ids(&store, &["営業議事録"]).contains(&"1".to_string()));
+        // AND 検索と、絞り込めない短い語
+        assert!(candidate_ids(&store, &["proposal", "議事録"]).is_empty());
+        assert_eq!(candidate_ids(&store, &["pp"]).len(), 4);
+    }
+
+    #[test]
+    fn rebuilds_schema_after_version_change() {
+        let dir = temp_dir();
+        let path = dir.path().join("drive_files.db");
+        let mut store = SqliteStore::new(path.clone()).unwrap();
+        store.save_data(&sample_files(), &HashMap::new(), &HashMap::new(), &HashMap::new(), None).unwrap();
+        drop(store);
+
+        Connection::open(&path).unwrap().pragma_update(None, "user_version", SCHEMA_VERSION - 1).unwrap();
+        let store = SqliteStore::new(path.clone()).unwrap();
+        assert_eq!(store.get_file_count().unwrap(), 0);
+        assert!(store.load_data().unwrap().is_none());
+
+        let version: i64 = store.lock().unwrap().pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+    }
+}
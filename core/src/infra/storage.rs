@@ -4,32 +4,137 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tracing::{debug, warn};
 
-use crate::models::DriveFile;
+use super::config::FolderSettings;
+use super::fs_util::write_atomic;
+use super::index_store::IndexStore;
+use super::ngram_index::NgramIndex;
+use crate::error::Error;
+use crate::models::{DriveFile, FileOwner};
+use crate::text::normalize::normalize;
+use crate::text::reading::readings_for;
+use crate::text::romaji::{kana_runs_to_canonical, kana_runs_to_romaji_variants};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonStorageFile {
     pub id: String,
     pub name: String,
+    // 検索用に正規化した名前（text::normalize）
+    #[serde(default)]
+    pub normalized_name: String,
     pub web_view_link: String,
     pub modified_time: DateTime<Utc>,
     pub mime_type: String,
     pub parents: Vec<String>,
     pub parent_folder_name: String,
+    #[serde(default)]
+    pub folder_path: Vec<String>,
+    // フォルダ経路を名前で表したもの（例: "Team / 2025 / Q3"）
+    #[serde(default)]
+    pub folder_breadcrumb: String,
     pub keywords: Vec<String>,
+    // かな部分をヘボン式・訓令式・日本式で表記したもの
     pub romaji_keywords: Vec<String>,
+    // 表記ゆれを吸収した比較用のローマ字（canonical_romaji）
+    #[serde(default)]
+    pub romaji_canonical: Vec<String>,
+    #[serde(default)]
+    pub owners: Vec<FileOwner>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl JsonStorageFile {
+    pub fn into_drive_file(self) -> DriveFile {
+        DriveFile::new(
+            self.id,
+            self.name,
+            self.web_view_link,
+            self.modified_time,
+            self.mime_type,
+            self.parents,
+        )
+        .with_folder_path(self.folder_path)
+        .with_owners(self.owners)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonStorageData {
     pub files: Vec<JsonStorageFile>,
+    // フォルダIDと名前の対応（対象フォルダと、辿ったサブフォルダ）
     pub folders: HashMap<String, String>,
+    // クロールしたフォルダIDごとの、対象フォルダからの経路
+    #[serde(default)]
+    pub folder_paths: HashMap<String, Vec<String>>,
+    // 同期時のフォルダ取得設定（設定変更の検知に使う）
+    #[serde(default)]
+    pub folder_settings: HashMap<String, FolderSettings>,
     pub last_sync: DateTime<Utc>,
     pub sync_token: Option<String>,
 }
 
+pub const BREADCRUMB_SEPARATOR: &str = " / ";
+
+// フォルダIDの経路を名前のパンくずに変換する
+pub fn build_breadcrumb(folder_path: &[String], folder_names: &HashMap<String, String>) -> String {
+    folder_path.iter()
+        .map(|folder_id| folder_names.get(folder_id).map(|name| name.as_str()).unwrap_or("不明なフォルダ"))
+        .collect::<Vec<_>>()
+        .join(BREADCRUMB_SEPARATOR)
+}
+
+// 同期したファイルに、検索用のキーワードとフォルダ情報を付与する（各バックエンド共通）
+pub fn build_storage_files(files: &[DriveFile], folder_names: &HashMap<String, String>) -> Vec<JsonStorageFile> {
+    let mut storage_files = Vec::new();
+
+    for file in files {
+        let normalized_name = normalize(&file.name);
+        let (keywords, romaji_keywords, romaji_canonical) = extract_keywords(&file.name, &normalized_name);
+
+        // 辿った経路の末尾（直接の親）を優先し、なければAPI上の親から引く
+        let parent_folder_name = file.folder_path.last()
+            .into_iter()
+            .chain(file.parents.iter())
+            .find_map(|folder_id| folder_names.get(folder_id))
+            .cloned()
+            .unwrap_or_else(|| "不明なフォルダ".to_string());
+
+        let folder_breadcrumb = if file.folder_path.is_empty() {
+            parent_folder_name.clone()
+        } else {
+            build_breadcrumb(&file.folder_path, folder_names)
+        };
+
+        storage_files.push(JsonStorageFile {
+            id: file.id.clone(),
+            name: file.name.clone(),
+            normalized_name,
+            web_view_link: file.web_view_link.clone(),
+            modified_time: file.modified_time,
+            mime_type: file.mime_type.clone(),
+            parents: file.parents.clone(),
+            parent_folder_name,
+            folder_path: file.folder_path.clone(),
+            folder_breadcrumb,
+            keywords,
+            romaji_keywords,
+            romaji_canonical,
+            owners: file.owners.clone(),
+        });
+    }
+
+    storage_files
+}
+
 pub struct JsonStorage {
     storage_path: PathBuf,
+    // 検索候補の絞り込みに使う n-gram 索引（drive_files.ngram）
+    index_path: PathBuf,
+    // 読み込み済みのデータ（ファイルの更新日時が変わるまで再利用する）
+    cache: Mutex<Option<(SystemTime, Arc<JsonStorageData>)>>,
+    index_cache: Mutex<Option<(SystemTime, Arc<NgramIndex>)>>,
 }
 
 impl JsonStorage {
@@ -39,254 +144,187 @@ impl JsonStorage {
         }
 
         Ok(Self {
+            index_path: storage_path.with_extension("ngram"),
             storage_path,
+            cache: Mutex::new(None),
+            index_cache: Mutex::new(None),
         })
     }
 
-    pub fn save_data(
+    fn cached_data(&self) -> Result<Option<Arc<JsonStorageData>>> {
+        let modified = match fs::metadata(&self.storage_path) {
+            Ok(metadata) => metadata.modified()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut cache = self.cache.lock().map_err(|_| anyhow::anyhow!("ストレージのキャッシュが破損しています"))?;
+        if let Some((cached_at, ref data)) = *cache {
+            if cached_at == modified {
+                return Ok(Some(data.clone()));
+            }
+        }
+
+        let json_data = fs::read_to_string(&self.storage_path)?;
+        let storage_data: Arc<JsonStorageData> = Arc::new(serde_json::from_str(&json_data).map_err(|e| {
+            Error::StorageCorrupted(format!("保存データを読み込めません（{}）: {}", self.storage_path.display(), e))
+        })?);
+        *cache = Some((modified, storage_data.clone()));
+        Ok(Some(storage_data))
+    }
+
+    // 索引が無い・古い（形式の変更や同期後の不一致）場合はデータから作り直して保存する
+    fn cached_index(&self, data: &JsonStorageData) -> Result<Arc<NgramIndex>> {
+        let modified = fs::metadata(&self.storage_path)?.modified()?;
+        let mut index_cache = self.index_cache.lock()
+            .map_err(|_| anyhow::anyhow!("索引のキャッシュが破損しています"))?;
+        if let Some((cached_at, ref index)) = *index_cache {
+            if cached_at == modified && index.is_current(data) {
+                return Ok(index.clone());
+            }
+        }
+
+        let index = match NgramIndex::load(&self.index_path)? {
+            Some(index) if index.is_current(data) => index,
+            _ => {
+                let index = NgramIndex::build(data);
+                if let Err(e) = index.save(&self.index_path) {
+                    warn!("検索用の索引を保存できませんでした: {:#}", e);
+                }
+                index
+            }
+        };
+
+        let index = Arc::new(index);
+        *index_cache = Some((modified, index.clone()));
+        Ok(index)
+    }
+}
+
+impl IndexStore for JsonStorage {
+    fn save_data(
         &mut self,
         files: &[DriveFile],
         folder_names: &HashMap<String, String>,
+        folder_paths: &HashMap<String, Vec<String>>,
+        folder_settings: &HashMap<String, FolderSettings>,
         sync_token: Option<String>,
     ) -> Result<()> {
-        let mut storage_files = Vec::new();
-
-        for file in files {
-            let (keywords, romaji_keywords) = self.extract_keywords(&file.name)?;
-
-            let parent_folder_name = file.parents.first()
-                .and_then(|parent_id| folder_names.get(parent_id))
-                .cloned()
-                .unwrap_or_else(|| "不明なフォルダ".to_string());
-
-            storage_files.push(JsonStorageFile {
-                id: file.id.clone(),
-                name: file.name.clone(),
-                web_view_link: file.web_view_link.clone(),
-                modified_time: file.modified_time,
-                mime_type: file.mime_type.clone(),
-                parents: file.parents.clone(),
-                parent_folder_name,
-                keywords,
-                romaji_keywords,
-            });
-        }
-
         let storage_data = JsonStorageData {
-            files: storage_files,
+            files: build_storage_files(files, folder_names),
             folders: folder_names.clone(),
+            folder_paths: folder_paths.clone(),
+            folder_settings: folder_settings.clone(),
             last_sync: Utc::now(),
             sync_token,
         };
 
-        let json_data = serde_json::to_string_pretty(&storage_data)?;
-        fs::write(&self.storage_path, json_data)?;
+        let json_data = serde_json::to_string(&storage_data)?;
+        write_atomic(&self.storage_path, json_data.as_bytes())?;
 
-        println!("JSONストレージに{}件のファイルを保存しました", files.len());
-        Ok(())
-    }
+        // 索引の保存に失敗しても、検索時に作り直されるので同期は失敗にしない
+        let index = NgramIndex::build(&storage_data);
+        if let Err(e) = index.save(&self.index_path) {
+            warn!("検索用の索引を保存できませんでした: {:#}", e);
+        }
 
-    pub fn load_data(&self) -> Result<Option<JsonStorageData>> {
-        if !self.storage_path.exists() {
-            return Ok(None);
+        let modified = fs::metadata(&self.storage_path)?.modified()?;
+        if let Ok(mut cache) = self.cache.lock() {
+            *cache = Some((modified, Arc::new(storage_data)));
+        }
+        if let Ok(mut index_cache) = self.index_cache.lock() {
+            *index_cache = Some((modified, Arc::new(index)));
         }
 
-        let json_data = fs::read_to_string(&self.storage_path)?;
-        let storage_data: JsonStorageData = serde_json::from_str(&json_data)?;
-        Ok(Some(storage_data))
+        debug!("JSONストレージに{}件のファイルを保存しました", files.len());
+        Ok(())
     }
 
-    pub fn get_files(&self) -> Result<Vec<DriveFile>> {
-        if let Some(data) = self.load_data()? {
-            let files: Vec<DriveFile> = data.files.into_iter().map(|f| {
-                DriveFile::new(
-                    f.id,
-                    f.name,
-                    f.web_view_link,
-                    f.modified_time,
-                    f.mime_type,
-                    f.parents,
-                )
-            }).collect();
-            Ok(files)
+    fn load_data(&self) -> Result<Option<JsonStorageData>> {
+        Ok(self.cached_data()?.map(|data| (*data).clone()))
+    }
+
+    fn get_files(&self) -> Result<Vec<DriveFile>> {
+        if let Some(data) = self.cached_data()? {
+            Ok(data.files.iter().cloned().map(JsonStorageFile::into_drive_file).collect())
         } else {
             Ok(Vec::new())
         }
     }
 
-    pub fn get_file_count(&self) -> Result<usize> {
-        if let Some(data) = self.load_data()? {
-            Ok(data.files.len())
-        } else {
-            Ok(0)
-        }
+    fn get_file_count(&self) -> Result<usize> {
+        Ok(self.cached_data()?.map(|data| data.files.len()).unwrap_or(0))
     }
 
-    pub fn get_sync_info(&self) -> Result<Option<(DateTime<Utc>, Option<String>)>> {
-        if let Some(data) = self.load_data()? {
-            Ok(Some((data.last_sync, data.sync_token)))
-        } else {
-            Ok(None)
-        }
+    fn get_sync_info(&self) -> Result<Option<(DateTime<Utc>, Option<String>)>> {
+        Ok(self.cached_data()?.map(|data| (data.last_sync, data.sync_token.clone())))
     }
 
-    pub fn get_folder_names(&self) -> Result<HashMap<String, String>> {
-        if let Some(data) = self.load_data()? {
-            Ok(data.folders)
-        } else {
-            Ok(HashMap::new())
+    fn get_folder_names(&self) -> Result<HashMap<String, String>> {
+        Ok(self.cached_data()?.map(|data| data.folders.clone()).unwrap_or_default())
+    }
+
+    fn search_candidates(&self, words: &[&str]) -> Result<Vec<JsonStorageFile>> {
+        let Some(data) = self.cached_data()? else {
+            return Ok(Vec::new());
+        };
+
+        // 候補には綴り間違い・部分列で一致しうるものも含まれる。索引で絞り込めない場合は全件を対象にする
+        match self.cached_index(&data)?.candidates(words) {
+            Some(positions) => Ok(positions.into_iter().map(|position| data.files[position].clone()).collect()),
+            None => Ok(data.files.clone()),
         }
     }
+}
 
-    fn extract_keywords(&mut self, text: &str) -> Result<(Vec<String>, Vec<String>)> {
-        let keywords = vec![text.to_string()];
-        let romaji_keywords = self.simple_kana_to_romaji(text);
-        Ok((keywords, romaji_keywords))
+// キーワードは正規化済みの名前と読み、ローマ字は正規化済みの名前のかな部分から作る
+// （半角カナも全角に揃えてから変換される）
+fn extract_keywords(text: &str, normalized: &str) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let mut keywords = vec![normalized.to_string()];
+    let mut romaji_keywords = kana_runs_to_romaji_variants(normalized);
+    let mut romaji_canonical = kana_runs_to_canonical(normalized);
+
+    // 漢字の読み（japanese-tokenizer 機能が有効な場合のみ）もローマ字で検索できるようにする
+    for reading in readings_for(text) {
+        let reading = normalize(&reading);
+        romaji_keywords.extend(kana_runs_to_romaji_variants(&reading));
+        romaji_canonical.extend(kana_runs_to_canonical(&reading));
+        keywords.push(reading);
     }
 
-    fn simple_kana_to_romaji(&self, text: &str) -> Vec<String> {
-        let mut romaji_parts = Vec::new();
-        let mut current_kana = String::new();
-        
-        for c in text.chars() {
-            match c {
-                // ひらがな
-                'あ' => current_kana.push_str("a"),
-                'い' => current_kana.push_str("i"),
-                'う' => current_kana.push_str("u"),
-                'え' => current_kana.push_str("e"),
-                'お' => current_kana.push_str("o"),
-                'か' => current_kana.push_str("ka"),
-                'き' => current_kana.push_str("ki"),
-                'く' => current_kana.push_str("ku"),
-                'け' => current_kana.push_str("ke"),
-                'こ' => current_kana.push_str("ko"),
-                'が' => current_kana.push_str("ga"),
-                'ぎ' => current_kana.push_str("gi"),
-                'ぐ' => current_kana.push_str("gu"),
-                'げ' => current_kana.push_str("ge"),
-                'ご' => current_kana.push_str("go"),
-                'さ' => current_kana.push_str("sa"),
-                'し' => current_kana.push_str("shi"),
-                'す' => current_kana.push_str("su"),
-                'せ' => current_kana.push_str("se"),
-                'そ' => current_kana.push_str("so"),
-                'ざ' => current_kana.push_str("za"),
-                'じ' => current_kana.push_str("ji"),
-                'ず' => current_kana.push_str("zu"),
-                'ぜ' => current_kana.push_str("ze"),
-                'ぞ' => current_kana.push_str("zo"),
-                'た' => current_kana.push_str("ta"),
-                'ち' => current_kana.push_str("chi"),
-                'つ' => current_kana.push_str("tsu"),
-                'て' => current_kana.push_str("te"),
-                'と' => current_kana.push_str("to"),
-                'だ' => current_kana.push_str("da"),
-                'ぢ' => current_kana.push_str("di"),
-                'づ' => current_kana.push_str("du"),
-                'で' => current_kana.push_str("de"),
-                'ど' => current_kana.push_str("do"),
-                'な' => current_kana.push_str("na"),
-                'に' => current_kana.push_str("ni"),
-                'ぬ' => current_kana.push_str("nu"),
-                'ね' => current_kana.push_str("ne"),
-                'の' => current_kana.push_str("no"),
-                'は' => current_kana.push_str("ha"),
-                'ひ' => current_kana.push_str("hi"),
-                'ふ' => current_kana.push_str("fu"),
-                'へ' => current_kana.push_str("he"),
-                'ほ' => current_kana.push_str("ho"),
-                'ば' => current_kana.push_str("ba"),
-                'び' => current_kana.push_str("bi"),
-                'ぶ' => current_kana.push_str("bu"),
-                'べ' => current_kana.push_str("be"),
-                'ぼ' => current_kana.push_str("bo"),
-                'ぱ' => current_kana.push_str("pa"),
-                'ぴ' => current_kana.push_str("pi"),
-                'ぷ' => current_kana.push_str("pu"),
-                'ぺ' => current_kana.push_str("pe"),
-                'ぽ' => current_kana.push_str("po"),
-                'ま' => current_kana.push_str("ma"),
-                'み' => current_kana.push_str("mi"),
-                'む' => current_kana.push_str("mu"),
-                'め' => current_kana.push_str("me"),
-                'も' => current_kana.push_str("mo"),
-                'や' => current_kana.push_str("ya"),
-                'ゆ' => current_kana.push_str("yu"),
-                'よ' => current_kana.push_str("yo"),
-                'ら' => current_kana.push_str("ra"),
-                'り' => current_kana.push_str("ri"),
-                'る' => current_kana.push_str("ru"),
-                'れ' => current_kana.push_str("re"),
-                'ろ' => current_kana.push_str("ro"),
-                'わ' => current_kana.push_str("wa"),
-                'ゐ' => current_kana.push_str("wi"),
-                'ゑ' => current_kana.push_str("we"),
-                'を' => current_kana.push_str("wo"),
-                'ん' => current_kana.push_str("n"),
-                // カタカナ
-                'ア' => current_kana.push_str("a"),
-                'イ' => current_kana.push_str("i"),
-                'ウ' => current_kana.push_str("u"),
-                'エ' => current_kana.push_str("e"),
-                'オ' => current_kana.push_str("o"),
-                'カ' => current_kana.push_str("ka"),
-                'キ' => current_kana.push_str("ki"),
-                'ク' => current_kana.push_str("ku"),
-                'ケ' => current_kana.push_str("ke"),
-                'コ' => current_kana.push_str("ko"),
-                'サ' => current_kana.push_str("sa"),
-                'シ' => current_kana.push_str("shi"),
-                'ス' => current_kana.push_str("su"),
-                'セ' => current_kana.push_str("se"),
-                'ソ' => current_kana.push_str("so"),
-                'タ' => current_kana.push_str("ta"),
-                'チ' => current_kana.push_str("chi"),
-                'ツ' => current_kana.push_str("tsu"),
-                'テ' => current_kana.push_str("te"),
-                'ト' => current_kana.push_str("to"),
-                'ナ' => current_kana.push_str("na"),
-                'ニ' => current_kana.push_str("ni"),
-                'ヌ' => current_kana.push_str("nu"),
-                'ネ' => current_kana.push_str("ne"),
-                'ノ' => current_kana.push_str("no"),
-                'ハ' => current_kana.push_str("ha"),
-                'ヒ' => current_kana.push_str("hi"),
-                'フ' => current_kana.push_str("fu"),
-                'ヘ' => current_kana.push_str("he"),
-                'ホ' => current_kana.push_str("ho"),
-                'マ' => current_kana.push_str("ma"),
-                'ミ' => current_kana.push_str("mi"),
-                'ム' => current_kana.push_str("mu"),
-                'メ' => current_kana.push_str("me"),
-                'モ' => current_kana.push_str("mo"),
-                'ヤ' => current_kana.push_str("ya"),
-                'ユ' => current_kana.push_str("yu"),
-                'ヨ' => current_kana.push_str("yo"),
-                'ラ' => current_kana.push_str("ra"),
-                'リ' => current_kana.push_str("ri"),
-                'ル' => current_kana.push_str("ru"),
-                'レ' => current_kana.push_str("re"),
-                'ロ' => current_kana.push_str("ro"),
-                'ワ' => current_kana.push_str("wa"),
-                'ヲ' => current_kana.push_str("wo"),
-                'ン' => current_kana.push_str("n"),
-                _ => {
-                    if !current_kana.is_empty() {
-                        romaji_parts.push(current_kana.clone());
-                        current_kana.clear();
-                    }
-                }
-            }
-        }
-        
-        if !current_kana.is_empty() {
-            romaji_parts.push(current_kana);
-        }
-        
-        romaji_parts.sort();
-        romaji_parts.dedup();
-        romaji_parts
+    for list in [&mut keywords, &mut romaji_keywords, &mut romaji_canonical] {
+        let mut seen = std::collections::HashSet::new();
+        list.retain(|item| seen.insert(item.clone()));
     }
 
-}
\ No newline at end of file
+    (keywords, romaji_keywords, romaji_canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keywords_for(name: &str) -> (Vec<String>, Vec<String>, Vec<String>) {
+        extract_keywords(name, &normalize(name))
+    }
+
+    #[cfg(not(feature = "japanese-tokenizer"))]
+    #[test]
+    fn extracts_kana_keywords_without_the_tokenizer() {
+        assert_eq!(keywords_for("議事録"), (vec!["議事録".to_string()], Vec::new(), Vec::new()));
+        assert_eq!(
+            keywords_for("キャンプ資料_v2"),
+            (vec!["きゃんぷ資料_v2".to_string()], vec!["kyanpu".to_string()], vec!["kyanpu".to_string()])
+        );
+    }
+
+    #[cfg(feature = "japanese-tokenizer")]
+    #[test]
+    fn adds_kanji_readings_with_the_tokenizer() {
+        let (keywords, romaji_keywords, romaji_canonical) = keywords_for("議事録");
+        assert_eq!(keywords[0], "議事録");
+        assert!(keywords.contains(&"ぎじろく".to_string()));
+        assert!(romaji_keywords.contains(&"gijiroku".to_string()));
+        assert!(romaji_canonical.contains(&"gijiroku".to_string()));
+    }
+}
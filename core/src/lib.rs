@@ -1,6 +1,14 @@
+pub mod error;
 pub mod models;
 pub mod services;
 pub mod infra;
+pub mod logging;
+pub mod output;
+pub mod text;
 
+#[cfg(test)]
+mod test_util;
+
+pub use error::Error;
 pub use models::*;
-pub use services::*;
\ No newline at end of file
+pub use services::*;
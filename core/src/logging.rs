@@ -0,0 +1,192 @@
+// 診断用のログ（tracing）の出力先・形式の設定と、ログに出す値からの秘密情報の除去
+// 標準出力は結果だけに使うので、ログは標準エラー出力かログファイルに書く
+
+use anyhow::Result;
+use serde_json::Value;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::str::FromStr;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::EnvFilter;
+use url::Url;
+
+// 設定するとレベルより優先される（例: FUZZY_DRIVE_SEARCH_LOG=fuzzy_drive_search_core::infra=trace）
+pub const LOG_FILTER_ENV: &str = "FUZZY_DRIVE_SEARCH_LOG";
+// ログファイルを残す日数
+const MAX_LOG_FILES: usize = 7;
+// trace で出す本文の最大文字数
+const MAX_LOGGED_BODY_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub const NAMES: [&'static str; 2] = ["text", "json"];
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::anyhow!("不明なログ形式です: {}（{} のいずれか）", value, Self::NAMES.join(", "))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LogOptions {
+    // このアプリのログの最低レベル（依存クレートは警告以上）
+    pub level: tracing::Level,
+    pub format: LogFormat,
+    // 真なら標準エラー出力ではなく、設定ディレクトリの logs/ に日ごとのファイルで書く
+    pub to_file: bool,
+}
+
+// ログの出力を始める。ファイルに書く場合、返り値を保持している間に書き込まれたものが保存される
+pub fn init(options: LogOptions, config_dir: &Path) -> Result<Option<WorkerGuard>> {
+    let filter = match std::env::var(LOG_FILTER_ENV) {
+        Ok(directives) => EnvFilter::try_new(directives)?,
+        Err(_) => {
+            let level = options.level.to_string().to_lowercase();
+            EnvFilter::try_new(format!("warn,fuzzy_drive_search_core={level},fuzzy_drive_search={level}"))?
+        }
+    };
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    if options.to_file {
+        // 古いファイルを消すときにディレクトリを読むので、先に作っておく
+        let log_dir = config_dir.join("logs");
+        std::fs::create_dir_all(&log_dir)?;
+        let appender = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("fuzzy-drive-search")
+            .filename_suffix("log")
+            .max_log_files(MAX_LOG_FILES)
+            .build(log_dir)?;
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+        let builder = builder.with_writer(writer).with_ansi(false);
+        match options.format {
+            LogFormat::Text => builder.try_init(),
+            LogFormat::Json => builder.json().try_init(),
+        }
+        .map_err(|e| anyhow::anyhow!(e))?;
+        return Ok(Some(guard));
+    }
+
+    // 端末に出すときは、時刻を省いて読みやすくする
+    let builder = builder
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    match options.format {
+        LogFormat::Text => builder.without_time().with_target(options.level >= tracing::Level::DEBUG).try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    }
+    .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(None)
+}
+
+// クエリ・本文の中で値を伏せるキー（認可コードや PKCE の検証子を含む）
+const SECRET_KEYS: [&str; 8] = [
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "client_secret",
+    "code",
+    "code_verifier",
+    "key",
+    "assertion",
+];
+const REDACTED: &str = "[REDACTED]";
+
+fn is_secret_key(key: &str) -> bool {
+    SECRET_KEYS.contains(&key)
+}
+
+// URL のクエリに含まれる秘密情報を伏せる
+pub fn redact_url(url: &Url) -> String {
+    if !url.query_pairs().any(|(key, _)| is_secret_key(&key)) {
+        return url.to_string();
+    }
+
+    let pairs: Vec<(String, String)> = url.query_pairs()
+        .map(|(key, value)| {
+            let value = if is_secret_key(&key) { REDACTED.to_string() } else { value.into_owned() };
+            (key.into_owned(), value)
+        })
+        .collect();
+    let mut redacted = url.clone();
+    redacted.query_pairs_mut().clear().extend_pairs(pairs);
+    redacted.to_string()
+}
+
+// レスポンスやリクエストの本文（JSON またはフォーム形式）の秘密情報を伏せ、長ければ切り詰める
+pub fn redact_body(body: &str) -> String {
+    let redacted = match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact_json(&mut value);
+            value.to_string()
+        }
+        Err(_) if body.contains('=') && !body.contains(char::is_whitespace) => {
+            url::form_urlencoded::parse(body.as_bytes())
+                .map(|(key, value)| {
+                    let value = if is_secret_key(&key) { REDACTED.into() } else { value };
+                    format!("{}={}", key, value)
+                })
+                .collect::<Vec<_>>()
+                .join("&")
+        }
+        Err(_) => body.to_string(),
+    };
+
+    if redacted.chars().count() > MAX_LOGGED_BODY_CHARS {
+        let truncated: String = redacted.chars().take(MAX_LOGGED_BODY_CHARS).collect();
+        format!("{}…（{}文字）", truncated, redacted.chars().count())
+    } else {
+        redacted
+    }
+}
+
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                // エラー応答の "code": 403 などの数値は残す
+                if is_secret_key(key) && value.is_string() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_tokens_in_urls_and_bodies() {
+        let url = Url::parse("https://example.com/callback?state=abc&code=4%2F0secret&key=AIza").unwrap();
+        assert_eq!(redact_url(&url), "https://example.com/callback?state=abc&code=%5BREDACTED%5D&key=%5BREDACTED%5D");
+
+        let body = r#"{"access_token":"ya29.secret","expires_in":3599,"nested":[{"refresh_token":"1//secret"}],"error":{"code":403}}"#;
+        let redacted = redact_body(body);
+        assert!(!redacted.contains("secret"));
+        assert!(redacted.contains(r#""expires_in":3599"#));
+        assert!(redacted.contains(r#""code":403"#));
+
+        assert_eq!(redact_body("grant_type=refresh_token&refresh_token=1%2F%2Fsecret"), "grant_type=refresh_token&refresh_token=[REDACTED]");
+        assert_eq!(redact_body("<html>Bad Gateway</html>"), "<html>Bad Gateway</html>");
+    }
+}
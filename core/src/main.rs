@@ -1,116 +1,805 @@
-use fuzzy_drive_search_core::services::SearchService;
-use serde_json;
-use std::env;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use fuzzy_drive_search_core::infra::{parse_folder_id, ConfigManager, FolderSettings, StorageBackend};
+use fuzzy_drive_search_core::logging::{self, LogFormat, LogOptions};
+use fuzzy_drive_search_core::models::{DriveFile, ProgressSender};
+use fuzzy_drive_search_core::output::{ndjson, print_output, ItemError, OutputError, OutputFormat, OutputItem, OutputMessage};
+use fuzzy_drive_search_core::services::search::query::{parse_query, QueryDiagnostic, QueryParseError};
+use fuzzy_drive_search_core::services::{DoctorService, SearchService};
+use fuzzy_drive_search_core::Error;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing::{info, warn, Level};
+
+#[derive(Parser)]
+#[command(name = "fuzzy-drive-search", version, about = "Google Drive のファイルをファジー検索します")]
+struct Cli {
+    /// 設定ディレクトリ（既定: ~/.config/fuzzy-drive-search）
+    #[arg(long, global = true, value_name = "DIR")]
+    config_dir: Option<PathBuf>,
+
+    /// プロファイル名（設定ディレクトリの profiles/<NAME> を使う）
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// 出力形式
+    #[arg(
+        long,
+        global = true,
+        value_name = "FORMAT",
+        default_value = "alfred",
+        value_parser = PossibleValuesParser::new(OutputFormat::NAMES)
+            .map(|value| value.parse::<OutputFormat>().expect("NAMES に含まれる形式"))
+    )]
+    format: OutputFormat,
+
+    /// ログを詳しくする（-v でデバッグ、-vv で通信内容まで）。エラーは原因の連鎖まで表示する
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// 警告とエラー以外のログを出さない
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// ログの形式
+    #[arg(
+        long,
+        global = true,
+        value_name = "FORMAT",
+        default_value = "text",
+        value_parser = PossibleValuesParser::new(LogFormat::NAMES)
+            .map(|value| value.parse::<LogFormat>().expect("NAMES に含まれる形式"))
+    )]
+    log_format: LogFormat,
+
+    /// ログを標準エラー出力ではなく、設定ディレクトリの logs/ に日ごとのファイルで書く
+    #[arg(long, global = true)]
+    log_file: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Google Drive認証と初回同期を実行する
+    Init {
+        #[arg(long, value_name = "ID")]
+        client_id: Option<String>,
+        #[arg(long, value_name = "SECRET")]
+        client_secret: Option<String>,
+    },
+    /// 設定されたフォルダのファイル一覧を同期する
+    Sync {
+        /// 進捗を1行ずつ NDJSON で出力する（結果とエラーも NDJSON になる）
+        #[arg(long)]
+        progress: bool,
+    },
+    /// 同期済みのファイルをファジー検索する（例: type:sheet folder:営業 modified:>2025-01-01 "語句" -draft）
+    ///
+    /// - で始まる除外条件は、クエリ全体を引用符で囲むか -- の後に書く（例: search -- foo -draft）
+    Search {
+        #[arg(required = true, value_name = "QUERY")]
+        query: Vec<String>,
+        /// 表示する件数
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// 同期済みのファイル数や最終同期日時を表示する
+    Status,
+    /// 設定・認証・保存データ・Drive API への接続を診断する
+    Doctor,
+    /// 設定を表示・変更する
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// 認証を管理する
+    #[command(subcommand)]
+    Auth(AuthCommand),
+    /// 検索対象フォルダを管理する
+    #[command(subcommand)]
+    Folders(FoldersCommand),
+    /// ファイルID、または検索結果の先頭のファイルを開く
+    Open {
+        #[arg(required = true, value_name = "ID_OR_QUERY")]
+        query: Vec<String>,
+        /// 開かずにURLだけを出力する
+        #[arg(long)]
+        print: bool,
+        /// 設定されたデスクトップアプリ用のURLで開く
+        #[arg(long)]
+        app: bool,
+    },
+    /// 同期済みのファイル一覧を書き出す
+    Export(OutputArgs),
+    /// ファイルを開いたことを記録し、よく使うファイルを検索結果の上位に表示する
+    RecordOpen {
+        file_id: String,
+    },
+    /// 開いた履歴を整理・書き出す
+    #[command(subcommand)]
+    History(HistoryCommand),
+    /// シェル補完スクリプトを出力する
+    Completions {
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// 現在の設定を表示する（シークレットは伏せる）
+    Show,
+    /// 設定ファイルのパスを出力する
+    Path,
+    /// 設定値を変更する
+    Set {
+        key: ConfigKey,
+        value: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ConfigKey {
+    ClientId,
+    ClientSecret,
+    StorageBackend,
+}
+
+#[derive(Subcommand)]
+enum AuthCommand {
+    /// ブラウザで認証する
+    Login {
+        /// 保存済みのトークンが有効でも認証し直す
+        #[arg(long)]
+        force: bool,
+    },
+    /// 保存済みのトークンを削除する
+    Logout,
+    /// 認証の状態を表示する
+    Status,
+}
+
+#[derive(Subcommand)]
+enum FoldersCommand {
+    /// 検索対象フォルダを一覧表示する
+    List,
+    /// 検索対象フォルダを追加する（既にあれば取得設定を更新する）
+    Add {
+        /// フォルダIDまたはフォルダのURL
+        folder: String,
+        /// サブフォルダも取得する
+        #[arg(long)]
+        recursive: bool,
+        /// 何階層下まで辿るか（--recursive を含む）
+        #[arg(long, value_name = "N")]
+        max_depth: Option<u32>,
+    },
+    /// 検索対象フォルダを削除する
+    Remove {
+        /// フォルダIDまたはフォルダのURL
+        folder: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// 古い記録を削除する（指定しなければ180日より古い記録）
+    Prune {
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<i64>,
+        /// 同期済みでないファイルの記録を削除する
+        #[arg(long)]
+        missing: bool,
+    },
+    /// 開いた履歴をJSONで書き出す
+    Export(OutputArgs),
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    /// 出力先（省略時は標準出力）
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    
-    // 引数の解析
-    if args.len() < 2 {
-        print_help();
-        return Ok(());
+async fn main() -> ExitCode {
+    // 使い方の誤りは clap が終了コード2で終了させる
+    let mut cli = Cli::parse();
+    // 進捗・結果・エラーを同じ NDJSON の流れとして読めるようにする
+    if matches!(cli.command, Command::Sync { progress: true }) {
+        cli.format = OutputFormat::Ndjson;
     }
+    let verbose = cli.verbose > 0;
+    let format = cli.format;
 
-    let command = &args[1];
-    
-    match command.as_str() {
-        "init" => {
-            handle_init(&args[2..]).await?;
-        }
-        "sync" => {
-            handle_sync().await?;
+    match run(cli).await {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            if verbose {
+                eprintln!("エラー: {:?}", e);
+            } else {
+                eprintln!("エラー: {:#}", e);
+            }
+
+            // 文脈を含むメッセージは分類する前に取っておく
+            let message = format!("{:#}", e);
+            let error = Error::from(e);
+            let output_error = output_error(&error, message);
+            format.print_error(&output_error);
+            ExitCode::from(output_error.exit_code)
         }
-        "--help" | "-h" | "help" => {
-            print_help();
+    }
+}
+
+// 失敗の種類ごとの終了コード（利用側が判定に使うので、番号は変えない）
+// 1: その他 / 2: 使い方の誤り / 3: 設定 / 4: 認証 / 5: ネットワーク / 6: Drive API / 7: 保存データの破損
+// 8: ファイルの読み書き / 9: 同期の実行中
+fn exit_code(error: &Error) -> u8 {
+    match error {
+        Error::InvalidQuery(_) => 2,
+        Error::ConfigMissing(_) | Error::ConfigInvalid(_) => 3,
+        Error::AuthRequired(_) | Error::AuthExpired(_) => 4,
+        Error::Network(_) => 5,
+        Error::DriveApi { .. } => 6,
+        Error::StorageCorrupted(_) => 7,
+        Error::Io(_) => 8,
+        Error::SyncInProgress => 9,
+        Error::Other(_) => 1,
+    }
+}
+
+fn output_error(error: &Error, message: String) -> OutputError {
+    let (status, reason) = match error {
+        Error::DriveApi { status, reason, .. } => (Some(*status), reason.clone()),
+        _ => (None, None),
+    };
+    OutputError {
+        code: error.code().to_string(),
+        message,
+        exit_code: exit_code(error),
+        status,
+        reason,
+    }
+}
+
+async fn run(cli: Cli) -> anyhow::Result<ExitCode> {
+    let format = cli.format;
+
+    if let Command::Completions { shell } = cli.command {
+        let mut command = Cli::command();
+        let name = command.get_name().to_string();
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut command, name, &mut script);
+        print_output(String::from_utf8(script)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let config_dir = resolve_config_dir(cli.config_dir, cli.profile.as_deref())?;
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => Level::WARN,
+        (false, 0) => Level::INFO,
+        (false, 1) => Level::DEBUG,
+        (false, _) => Level::TRACE,
+    };
+    let log_options = LogOptions {
+        level,
+        format: cli.log_format,
+        to_file: cli.log_file,
+    };
+    // ファイルに書く場合、終了までに書き込みを済ませるため保持しておく
+    let _log_guard = logging::init(log_options, &config_dir)?;
+    let config_manager = ConfigManager::with_config_dir(config_dir)?;
+
+    let result = match cli.command {
+        Command::Init { client_id, client_secret } => {
+            handle_init(config_manager, client_id, client_secret, format).await
         }
-        _ => {
-            print_help();
+        Command::Sync { progress } => handle_sync(config_manager, progress, format).await,
+        Command::Search { query, limit } => return handle_search(config_manager, &query.join(" "), limit, format),
+        Command::Status => handle_status(config_manager, format),
+        Command::Doctor => return handle_doctor(config_manager, format).await,
+        Command::Config(command) => handle_config(config_manager, command, format),
+        Command::Auth(command) => handle_auth(config_manager, command, format).await,
+        Command::Folders(command) => handle_folders(config_manager, command, format),
+        Command::Open { query, print, app } => handle_open(config_manager, &query.join(" "), print, app, format),
+        Command::Export(args) => handle_export(config_manager, args.output, format),
+        Command::RecordOpen { file_id } => handle_record_open(config_manager, &file_id, format),
+        Command::History(command) => handle_history(config_manager, command, format),
+        Command::Completions { .. } => unreachable!("補完スクリプトは設定を読む前に出力済み"),
+    };
+    result.map(|()| ExitCode::SUCCESS)
+}
+
+// --config-dir と --profile から設定ディレクトリを決める
+fn resolve_config_dir(config_dir: Option<PathBuf>, profile: Option<&str>) -> anyhow::Result<PathBuf> {
+    let base_dir = match config_dir {
+        Some(config_dir) => config_dir,
+        None => ConfigManager::default_config_dir()?,
+    };
+
+    let config_dir = match profile {
+        Some(profile) => {
+            let valid = !profile.is_empty()
+                && profile.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(anyhow::anyhow!(
+                    "プロファイル名には英数字・-・_ のみ使えます: {}",
+                    profile
+                ));
+            }
+            base_dir.join("profiles").join(profile)
         }
+        None => base_dir,
+    };
+    Ok(config_dir)
+}
+
+fn info_item(title: &str, subtitle: &str) -> OutputItem {
+    OutputItem {
+        title: title.to_string(),
+        subtitle: subtitle.to_string(),
+        ..Default::default()
     }
+}
 
-    Ok(())
+fn file_item(file: DriveFile, folder_names: &HashMap<String, String>) -> OutputItem {
+    let folder_name = SearchService::folder_breadcrumb(&file, folder_names);
+
+    OutputItem {
+        id: Some(file.id),
+        title: file.name,
+        subtitle: folder_name,
+        url: Some(file.web_view_link),
+        mime_type: Some(file.mime_type),
+        modified_time: Some(file.modified_time),
+        ..Default::default()
+    }
 }
 
-fn print_help() {
-    let help_items = vec![
-        serde_json::json!({
-            "title": "Fuzzy Drive Search - ヘルプ",
-            "subtitle": "使用方法: fuzzy-drive-search [init|sync]",
-            "valid": false
-        }),
-        serde_json::json!({
-            "title": "init - 初期設定",
-            "subtitle": "Google Drive認証と初回同期を実行します",
-            "valid": false
-        }),
-        serde_json::json!({
-            "title": "sync - 手動同期",
-            "subtitle": "設定された複数フォルダの直下ファイル一覧を強制同期します",
-            "valid": false
-        })
-    ];
-    
-    let output = serde_json::json!({
-        "items": help_items
-    });
-    println!("{}", serde_json::to_string_pretty(&output).unwrap_or_default());
-}
-
-async fn handle_init(args: &[String]) -> anyhow::Result<()> {
-    println!("Fuzzy Drive Search の初期化を開始します...");
-    
-    let (client_id, client_secret) = parse_auth_args(args)?;
-    let mut service = SearchService::new()?;
-    
+async fn handle_init(
+    config_manager: ConfigManager,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    info!("Fuzzy Drive Search の初期化を開始します...");
+
+    let mut service = SearchService::with_config_manager(config_manager)?;
+
     if client_id.is_some() || client_secret.is_some() {
         service.initialize_with_overrides(client_id, client_secret).await?;
     } else {
         service.ensure_initialized().await?;
     }
-    
-    println!("初期化が完了しました");
+
+    let file_count = service.status()?.file_count;
+    format.print_message(&OutputMessage::success(
+        "初期化が完了しました",
+        Some(format!("{}件のファイルを同期済みです", file_count)),
+    ));
     Ok(())
 }
 
-fn parse_auth_args(args: &[String]) -> anyhow::Result<(Option<String>, Option<String>)> {
-    let mut client_id = None;
-    let mut client_secret = None;
-    
-    let mut i = 0;
-    while i < args.len() {
-        let arg = &args[i];
-        
-        if let Some(value) = arg.strip_prefix("--client-id=") {
-            client_id = Some(value.to_string());
-            i += 1;
-        } else if let Some(value) = arg.strip_prefix("--client-secret=") {
-            client_secret = Some(value.to_string());
-            i += 1;
-        } else if arg == "--client-id" {
-            if i + 1 >= args.len() {
-                return Err(anyhow::anyhow!("--client-id には値が必要です"));
+async fn handle_sync(config_manager: ConfigManager, progress: bool, format: OutputFormat) -> anyhow::Result<()> {
+    let mut service = SearchService::with_config_manager(config_manager)?;
+
+    if progress {
+        let (sender, mut events) = ProgressSender::channel();
+        let print_events = async {
+            while let Some(event) = events.recv().await {
+                print_output(ndjson::format_progress(&event));
             }
-            client_id = Some(args[i + 1].clone());
-            i += 2;
-        } else if arg == "--client-secret" {
-            if i + 1 >= args.len() {
-                return Err(anyhow::anyhow!("--client-secret には値が必要です"));
+        };
+        let (result, ()) = tokio::join!(service.sync_files_with_progress(sender), print_events);
+        result?;
+    } else {
+        service.sync_files().await?;
+    }
+
+    let file_count = service.status()?.file_count;
+    format.print_message(&OutputMessage::success(
+        "同期が完了しました",
+        Some(format!("{}件のファイル", file_count)),
+    ));
+    Ok(())
+}
+
+fn storage_backend_name(storage_backend: StorageBackend) -> &'static str {
+    match storage_backend {
+        StorageBackend::Json => "json",
+        StorageBackend::Sqlite => "sqlite",
+    }
+}
+
+fn handle_status(config_manager: ConfigManager, format: OutputFormat) -> anyhow::Result<()> {
+    let service = SearchService::with_config_manager(config_manager)?;
+    let status = service.status()?;
+
+    let last_sync = status.last_sync
+        .map(|last_sync| last_sync.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "未同期".to_string());
+
+    let items = vec![
+        info_item("同期済みのファイル数", &status.file_count.to_string()),
+        info_item("最終同期", &last_sync),
+        info_item("対象フォルダ数", &status.target_folder_count.to_string()),
+        info_item("認証", if status.authenticated { "認証済み" } else { "未認証" }),
+        info_item("保存形式", storage_backend_name(status.storage_backend)),
+        info_item("開いた履歴のあるファイル数", &status.history_file_count.to_string()),
+        info_item("設定ディレクトリ", &status.config_dir.display().to_string()),
+    ];
+    format.print_items(&items);
+    Ok(())
+}
+
+// クエリの誤りは位置つきの項目として表示し、終了コードだけで失敗を伝える
+fn handle_search(config_manager: ConfigManager, query: &str, limit: usize, format: OutputFormat) -> anyhow::Result<ExitCode> {
+    let service = SearchService::with_config_manager(config_manager)?;
+
+    let parsed = match parse_query(query) {
+        Ok(parsed) => parsed,
+        Err(parse_error) => {
+            print_query_error(query, &parse_error, format);
+            return Ok(ExitCode::from(exit_code(&Error::InvalidQuery(parse_error))));
+        }
+    };
+    let results = service.search_parsed(&parsed, limit)?;
+    let folder_names = service.get_folder_names()?;
+
+    // クエリの注意は、選択できない項目として結果の先頭に出す
+    let items: Vec<OutputItem> = parsed.diagnostics.iter()
+        .map(|diagnostic| query_diagnostic_item(query, diagnostic))
+        .chain(results.into_iter().map(|result| OutputItem {
+            score: Some(result.score),
+            matched_ranges: result.matched_ranges,
+            ..file_item(result.file, &folder_names)
+        }))
+        .collect();
+
+    format.print_items(&items);
+    Ok(ExitCode::SUCCESS)
+}
+
+// 診断結果は出力済みなので、問題があってもエラーの出力はせず終了コードだけで伝える
+async fn handle_doctor(config_manager: ConfigManager, format: OutputFormat) -> anyhow::Result<ExitCode> {
+    let report = DoctorService::new(config_manager).run().await;
+    format.print_report(&report);
+
+    if report.has_failures() {
+        warn!("診断で問題が見つかりました");
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// シークレットは先頭の数文字だけを表示する
+fn mask_secret(secret: &str) -> String {
+    let visible: String = secret.chars().take(4).collect();
+    if secret.chars().count() <= 4 {
+        "****".to_string()
+    } else {
+        format!("{}****", visible)
+    }
+}
+
+fn handle_config(config_manager: ConfigManager, command: ConfigCommand, format: OutputFormat) -> anyhow::Result<()> {
+    let config_path = config_manager.config_dir.join("config.toml");
+
+    match command {
+        ConfigCommand::Show => {
+            let config = config_manager.load_config()?;
+            let items = vec![
+                info_item("設定ファイル", &config_path.display().to_string()),
+                info_item("Client ID", &config.google_client_id),
+                info_item("Client Secret", &mask_secret(&config.google_client_secret)),
+                info_item("保存形式", storage_backend_name(config.storage_backend)),
+                info_item("対象フォルダ", &config.target_folder_ids.join(", ")),
+            ];
+            format.print_items(&items);
+        }
+        ConfigCommand::Path => {
+            print_output(config_path.display().to_string());
+        }
+        ConfigCommand::Set { key, value } => {
+            let mut config = config_manager.load_config()?;
+            let name = match key {
+                ConfigKey::ClientId => {
+                    config.google_client_id = value;
+                    "Client ID"
+                }
+                ConfigKey::ClientSecret => {
+                    config.google_client_secret = value;
+                    "Client Secret"
+                }
+                ConfigKey::StorageBackend => {
+                    config.storage_backend = match value.to_lowercase().as_str() {
+                        "json" => StorageBackend::Json,
+                        "sqlite" => StorageBackend::Sqlite,
+                        _ => return Err(anyhow::anyhow!(
+                            "保存形式には json または sqlite を指定してください: {}",
+                            value
+                        )),
+                    };
+                    "保存形式"
+                }
+            };
+            config_manager.save_config(&config)?;
+            format.print_message(&OutputMessage::success(format!("{}を変更しました", name), None));
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_auth(config_manager: ConfigManager, command: AuthCommand, format: OutputFormat) -> anyhow::Result<()> {
+    match command {
+        AuthCommand::Login { force } => {
+            let service = SearchService::with_config_manager(config_manager)?;
+            service.login(force).await?;
+            format.print_message(&OutputMessage::success("認証済みです", None));
+        }
+        AuthCommand::Logout => {
+            let message = if config_manager.delete_tokens()? {
+                OutputMessage::success("認証トークンを削除しました", None)
+            } else {
+                OutputMessage::info("保存済みの認証トークンはありません", None)
+            };
+            format.print_message(&message);
+        }
+        AuthCommand::Status => {
+            let message = match config_manager.load_tokens()? {
+                Some(tokens) => OutputMessage::info(
+                    "認証済み",
+                    Some(if tokens.refresh_token.is_some() {
+                        "リフレッシュトークンあり".to_string()
+                    } else {
+                        "リフレッシュトークンなし（期限切れ後は再認証が必要です）".to_string()
+                    }),
+                ),
+                None => OutputMessage::info(
+                    "未認証",
+                    Some("fuzzy-drive-search auth login で認証してください".to_string()),
+                ),
+            };
+            format.print_message(&message);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_folders(config_manager: ConfigManager, command: FoldersCommand, format: OutputFormat) -> anyhow::Result<()> {
+    let mut config = config_manager.load_config()?;
+
+    match command {
+        FoldersCommand::List => {
+            let service = SearchService::with_config_manager(config_manager)?;
+            let folder_names = service.get_folder_names()?;
+
+            let items: Vec<OutputItem> = config.target_folder_ids.iter().map(|folder_id| {
+                let settings = config.folder_settings_for(folder_id);
+                let scope = match (settings.recursive, settings.max_depth) {
+                    (false, _) => "直下のみ".to_string(),
+                    (true, None) => "サブフォルダすべて".to_string(),
+                    (true, Some(depth)) => format!("{}階層下まで", depth),
+                };
+                OutputItem {
+                    id: Some(folder_id.clone()),
+                    title: folder_names.get(folder_id).cloned().unwrap_or_else(|| folder_id.clone()),
+                    subtitle: format!("{}（{}）", folder_id, scope),
+                    url: Some(format!("https://drive.google.com/drive/folders/{}", folder_id)),
+                    ..Default::default()
+                }
+            }).collect();
+            format.print_items(&items);
+        }
+        FoldersCommand::Add { folder, recursive, max_depth } => {
+            let folder_id = parse_folder_id(&folder);
+            if folder_id.is_empty() {
+                return Err(anyhow::anyhow!("フォルダIDを取り出せません: {}", folder));
+            }
+
+            let settings = FolderSettings {
+                recursive: recursive || max_depth.is_some(),
+                max_depth,
+            };
+            let title = if config.add_folder(&folder_id, settings) {
+                "検索対象フォルダを追加しました"
+            } else {
+                "検索対象フォルダの設定を更新しました"
+            };
+            config_manager.save_config(&config)?;
+            format.print_message(&OutputMessage::success(
+                title,
+                Some(format!("{}（反映するには sync を実行してください）", folder_id)),
+            ));
+        }
+        FoldersCommand::Remove { folder } => {
+            let folder_id = parse_folder_id(&folder);
+            if !config.remove_folder(&folder_id) {
+                return Err(anyhow::anyhow!("検索対象フォルダにありません: {}", folder_id));
             }
-            client_secret = Some(args[i + 1].clone());
-            i += 2;
-        } else {
-            i += 1;
+            config_manager.save_config(&config)?;
+            format.print_message(&OutputMessage::success(
+                "検索対象フォルダを削除しました",
+                Some(format!("{}（反映するには sync を実行してください）", folder_id)),
+            ));
         }
     }
-    
-    Ok((client_id, client_secret))
+
+    Ok(())
 }
 
+fn handle_open(
+    config_manager: ConfigManager,
+    id_or_query: &str,
+    print_only: bool,
+    prefer_app: bool,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let service = SearchService::with_config_manager(config_manager)?;
+    let file = service.resolve_file(id_or_query)?
+        .ok_or_else(|| anyhow::anyhow!("一致するファイルがありません: {}", id_or_query))?;
+    let url = service.open_url(&file, prefer_app)?;
+
+    if print_only {
+        print_output(url);
+        return Ok(());
+    }
 
-async fn handle_sync() -> anyhow::Result<()> {
-    let mut service = SearchService::new()?;
-    service.sync_files().await?;
-    println!("同期が完了しました");
+    open::that(&url)
+        .map_err(|e| anyhow::anyhow!("ファイルを開けませんでした: {} ({})", url, e))?;
+    service.record_open(&file.id)?;
+    format.print_message(&OutputMessage::success("ファイルを開きました", Some(file.name)));
     Ok(())
-}
\ No newline at end of file
+}
+
+fn handle_export(config_manager: ConfigManager, output: Option<PathBuf>, format: OutputFormat) -> anyhow::Result<()> {
+    let service = SearchService::with_config_manager(config_manager)?;
+    let folder_names = service.get_folder_names()?;
+
+    let items: Vec<OutputItem> = service.get_files()?
+        .into_iter()
+        .map(|file| file_item(file, &folder_names))
+        .collect();
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, format.formatter().format_items(&items))?;
+            format.print_message(&OutputMessage::success(
+                format!("{}件のファイルを書き出しました", items.len()),
+                Some(path.display().to_string()),
+            ));
+        }
+        None => format.print_items(&items),
+    }
+    Ok(())
+}
+
+fn handle_record_open(config_manager: ConfigManager, file_id: &str, format: OutputFormat) -> anyhow::Result<()> {
+    let service = SearchService::with_config_manager(config_manager)?;
+
+    let detail = match service.record_open(file_id)? {
+        Some(file) => file.name,
+        None => format!("{}（同期済みのファイルに見つかりません）", file_id),
+    };
+    format.print_message(&OutputMessage::success("開いた記録を追加しました", Some(detail)));
+    Ok(())
+}
+
+fn handle_history(config_manager: ConfigManager, command: HistoryCommand, format: OutputFormat) -> anyhow::Result<()> {
+    let service = SearchService::with_config_manager(config_manager)?;
+
+    match command {
+        HistoryCommand::Prune { older_than, missing } => {
+            // 何も指定しなければ180日より古い記録を削除する
+            let older_than = match (older_than, missing) {
+                (None, false) => Some(180),
+                (older_than, _) => older_than,
+            };
+
+            let removed = service.prune_history(older_than, missing)?;
+            format.print_message(&OutputMessage::success(format!("{}件の開いた記録を削除しました", removed), None));
+        }
+        HistoryCommand::Export(OutputArgs { output }) => {
+            let json_data = serde_json::to_string_pretty(&service.export_history()?)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, json_data)?;
+                    format.print_message(&OutputMessage::success(
+                        "開いた履歴を書き出しました",
+                        Some(path.display().to_string()),
+                    ));
+                }
+                None => print_output(json_data),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// クエリの構文エラーを、該当箇所を示した結果項目として出力する
+fn print_query_error(query: &str, error: &QueryParseError, format: OutputFormat) {
+    let (start, end) = error.span;
+    format.print_items(&[OutputItem {
+        title: error.to_string(),
+        subtitle: mark_span(query, error.span),
+        error: Some(ItemError {
+            message: error.message.clone(),
+            start,
+            end,
+        }),
+        ..Default::default()
+    }]);
+}
+
+fn query_diagnostic_item(query: &str, diagnostic: &QueryDiagnostic) -> OutputItem {
+    let (start, end) = diagnostic.span;
+    OutputItem {
+        title: diagnostic.to_string(),
+        subtitle: mark_span(query, diagnostic.span),
+        warning: Some(ItemError {
+            message: diagnostic.to_string(),
+            start,
+            end,
+        }),
+        ..Default::default()
+    }
+}
+
+// クエリの該当箇所を【】で囲む
+fn mark_span(query: &str, (start, end): (usize, usize)) -> String {
+    query.chars().enumerate()
+        .flat_map(|(i, c)| {
+            let open = (i == start).then_some('【');
+            let close = (i + 1 == end.max(start + 1)).then_some('】');
+            open.into_iter().chain(std::iter::once(c)).chain(close)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parses_queries_with_negations_and_global_flags() {
+        let cli = Cli::try_parse_from([
+            "fuzzy-drive-search", "search", "proposal", "--limit", "5", "--format", "text", "--", "-draft",
+        ]).unwrap();
+        assert_eq!(cli.format, OutputFormat::Text);
+        match cli.command {
+            Command::Search { query, limit } => {
+                assert_eq!(query, vec!["proposal", "-draft"]);
+                assert_eq!(limit, 5);
+            }
+            _ => panic!("search として解釈されていません"),
+        }
+
+        assert!(Cli::try_parse_from(["fuzzy-drive-search", "search", "proposal", "-draft"]).is_err());
+        assert!(Cli::try_parse_from(["fuzzy-drive-search", "unknown"]).is_err());
+        assert!(Cli::try_parse_from(["fuzzy-drive-search", "search", "x", "--format", "xml"]).is_err());
+        assert!(matches!(
+            Cli::try_parse_from(["fuzzy-drive-search", "sync", "--progress"]).unwrap().command,
+            Command::Sync { progress: true }
+        ));
+    }
+
+    #[test]
+    fn maps_errors_to_stable_exit_codes() {
+        assert_eq!(exit_code(&Error::ConfigMissing(String::new())), 3);
+        assert_eq!(exit_code(&Error::AuthExpired(String::new())), 4);
+        assert_eq!(exit_code(&Error::SyncInProgress), 9);
+
+        let error = output_error(&Error::from(anyhow::anyhow!("想定外")), "想定外".to_string());
+        assert_eq!((error.code.as_str(), error.exit_code), ("other", 1));
+    }
+}
@@ -9,6 +9,18 @@ pub struct DriveFile {
     pub modified_time: DateTime<Utc>,
     pub mime_type: String,
     pub parents: Vec<String>,
+    // 対象フォルダから辿ったフォルダIDの経路（対象フォルダ自身を含む）
+    #[serde(default)]
+    pub folder_path: Vec<String>,
+    // マイドライブのファイルの所有者（共有ドライブのファイルは空）
+    #[serde(default)]
+    pub owners: Vec<FileOwner>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileOwner {
+    pub display_name: String,
+    pub email_address: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,7 +53,19 @@ impl DriveFile {
             modified_time,
             mime_type,
             parents,
+            folder_path: Vec::new(),
+            owners: Vec::new(),
         }
     }
+
+    pub fn with_folder_path(mut self, folder_path: Vec<String>) -> Self {
+        self.folder_path = folder_path;
+        self
+    }
+
+    pub fn with_owners(mut self, owners: Vec<FileOwner>) -> Self {
+        self.owners = owners;
+        self
+    }
 }
 
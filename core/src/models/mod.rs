@@ -1,3 +1,5 @@
 pub mod entities;
+pub mod progress;
 
-pub use entities::*;
\ No newline at end of file
+pub use entities::*;
+pub use progress::*;
\ No newline at end of file
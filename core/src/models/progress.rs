@@ -0,0 +1,100 @@
+use serde::Serialize;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+// 同期の進み具合（UI が進捗バーなどを表示するためのもの）
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SyncProgress {
+    SyncStarted {
+        mode: SyncMode,
+        // 検索対象として設定されたフォルダの数
+        folder_count: usize,
+    },
+    FolderStarted {
+        folder_id: String,
+        // 対象フォルダからの階層（対象フォルダ自体は0）
+        depth: u32,
+    },
+    PageFetched {
+        folder_id: String,
+        // このページのファイル・サブフォルダの数と、このフォルダでのこれまでの合計
+        page_items: usize,
+        folder_items: usize,
+    },
+    FolderDone {
+        folder_id: String,
+        completed_folders: usize,
+        // 見つかっていて、まだ取得し終えていないフォルダの数
+        remaining_folders: usize,
+        // これまでに見つかったファイルの数
+        total_files: usize,
+    },
+    ChangesFetched {
+        change_count: usize,
+    },
+    StorageWritten {
+        file_count: usize,
+    },
+    // 同期は続く失敗（対象フォルダの名前が取れないなど）
+    // 同期自体が失敗した場合は、進捗ではなく同期の結果のエラーとして伝える
+    Error {
+        code: String,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    Full,
+    Incremental,
+}
+
+// 進捗の送り先。受け手がいない・閉じられた場合は何もしない
+#[derive(Debug, Clone, Default)]
+pub struct ProgressSender(Option<UnboundedSender<SyncProgress>>);
+
+impl ProgressSender {
+    // 送り先と受け手を作る。送り先（とその複製）がすべて破棄されると受け手の recv は None を返す
+    pub fn channel() -> (Self, UnboundedReceiver<SyncProgress>) {
+        let (sender, receiver) = unbounded_channel();
+        (Self(Some(sender)), receiver)
+    }
+
+    // 進捗を通知しない
+    pub fn disabled() -> Self {
+        Self(None)
+    }
+
+    pub fn send(&self, event: SyncProgress) {
+        if let Some(ref sender) = self.0 {
+            let _ = sender.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn delivers_events_until_senders_are_dropped() {
+        let (sender, mut receiver) = ProgressSender::channel();
+        let clone = sender.clone();
+        sender.send(SyncProgress::FolderStarted { folder_id: "root".to_string(), depth: 0 });
+        clone.send(SyncProgress::StorageWritten { file_count: 3 });
+        drop(sender);
+        drop(clone);
+
+        let first = receiver.recv().await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&first).unwrap(),
+            serde_json::json!({ "event": "folder_started", "folder_id": "root", "depth": 0 })
+        );
+        assert_eq!(receiver.recv().await, Some(SyncProgress::StorageWritten { file_count: 3 }));
+        assert_eq!(receiver.recv().await, None);
+
+        // 受け手がいなくても送れる
+        ProgressSender::disabled().send(SyncProgress::ChangesFetched { change_count: 1 });
+    }
+}
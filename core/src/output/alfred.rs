@@ -0,0 +1,107 @@
+// Alfred の Script Filter 形式（{"items": [...]}）
+
+use serde_json::{json, Value};
+
+use super::{Formatter, OutputError, OutputItem, OutputMessage};
+
+pub struct AlfredFormatter;
+
+impl Formatter for AlfredFormatter {
+    fn format_items(&self, items: &[OutputItem]) -> String {
+        let items: Vec<Value> = items.iter().map(alfred_item).collect();
+        to_pretty(&json!({ "items": items }))
+    }
+
+    fn format_message(&self, message: &OutputMessage) -> String {
+        let mut item = json!({
+            "title": message.title,
+            "subtitle": message.detail.clone().unwrap_or_default(),
+            "valid": false,
+        });
+        item["kind"] = json!(message.kind);
+        to_pretty(&json!({ "items": [item] }))
+    }
+
+    // 選択できない項目としてエラーを表示する
+    fn format_error(&self, error: &OutputError) -> String {
+        to_pretty(&json!({
+            "items": [{
+                "title": error.message,
+                "subtitle": format!("エラー: {}", error.code),
+                "valid": false,
+                "kind": "error",
+                "error": error,
+            }]
+        }))
+    }
+}
+
+fn alfred_item(item: &OutputItem) -> Value {
+    let mut value = json!({
+        "title": item.title,
+        "subtitle": item.subtitle,
+        "valid": item.url.is_some(),
+    });
+
+    if let Some(ref id) = item.id {
+        value["uid"] = json!(id);
+    }
+    if let Some(ref url) = item.url {
+        value["arg"] = json!(url);
+        value["quicklookurl"] = json!(url);
+        // ⌘ でファイルID、⌥ でURLを次のアクションに渡す
+        let mut mods = json!({
+            "alt": { "valid": true, "arg": url, "subtitle": url },
+        });
+        if let Some(ref id) = item.id {
+            mods["cmd"] = json!({ "valid": true, "arg": id, "subtitle": format!("ファイルID: {}", id) });
+        }
+        value["mods"] = mods;
+    }
+    if let Some(ref mime_type) = item.mime_type {
+        value["mimeType"] = json!(mime_type);
+        if let Some(uti) = file_type_uti(mime_type) {
+            value["icon"] = json!({ "type": "filetype", "path": uti });
+        }
+    }
+    if let Some(score) = item.score {
+        value["score"] = json!(score);
+        value["matchedRanges"] = json!(item.matched_ranges);
+    }
+    if let Some(ref error) = item.error {
+        value["error"] = json!(error);
+    }
+    if let Some(ref warning) = item.warning {
+        value["warning"] = json!(warning);
+    }
+
+    value
+}
+
+// Alfred のファイル種類アイコン（UTI）。Google 形式は近い種類のアイコンを使う
+fn file_type_uti(mime_type: &str) -> Option<&'static str> {
+    let uti = match mime_type {
+        "application/vnd.google-apps.folder" => "public.folder",
+        "application/vnd.google-apps.document"
+        | "application/msword"
+        | "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => "org.openxmlformats.wordprocessingml.document",
+        "application/vnd.google-apps.spreadsheet"
+        | "application/vnd.ms-excel"
+        | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => "org.openxmlformats.spreadsheetml.sheet",
+        "application/vnd.google-apps.presentation"
+        | "application/vnd.ms-powerpoint"
+        | "application/vnd.openxmlformats-officedocument.presentationml.presentation" => "org.openxmlformats.presentationml.presentation",
+        "application/pdf" => "com.adobe.pdf",
+        "text/csv" => "public.comma-separated-values-text",
+        "text/plain" => "public.plain-text",
+        mime if mime.starts_with("image/") => "public.image",
+        mime if mime.starts_with("video/") => "public.movie",
+        mime if mime.starts_with("audio/") => "public.audio",
+        _ => return None,
+    };
+    Some(uti)
+}
+
+fn to_pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
@@ -0,0 +1,193 @@
+// コマンドの結果を、利用側（Alfred・Raycast・スクリプト・人）に合わせた形式で出力する
+// 進捗などのログは標準エラー出力に書き、標準出力には結果だけを書く
+
+pub mod alfred;
+pub mod ndjson;
+pub mod raycast;
+pub mod text;
+pub mod tsv;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::services::{CheckStatus, DoctorReport};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Alfred,
+    Raycast,
+    Text,
+    Tsv,
+    Ndjson,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 5] = ["alfred", "raycast", "text", "tsv", "ndjson"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        match value.to_lowercase().as_str() {
+            "alfred" => Ok(OutputFormat::Alfred),
+            "raycast" => Ok(OutputFormat::Raycast),
+            "text" => Ok(OutputFormat::Text),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(anyhow::anyhow!(
+                "不明な出力形式です: {}（{} のいずれか）",
+                value,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+// 一覧の1項目（検索結果・ヘルプ・状態の各行など）
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OutputItem {
+    pub id: Option<String>,
+    pub title: String,
+    pub subtitle: String,
+    // 開くときのURL（無い項目は選択できない）
+    pub url: Option<String>,
+    pub mime_type: Option<String>,
+    pub modified_time: Option<DateTime<Utc>>,
+    pub score: Option<f64>,
+    pub matched_ranges: Vec<(usize, usize)>,
+    pub error: Option<ItemError>,
+    // 検索は行ったが知らせたいクエリの箇所（フィルタのキーの打ち間違いらしいものなど）
+    pub warning: Option<ItemError>,
+}
+
+// クエリの誤りや注意など、項目として表示するもの（範囲は文字単位）
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageKind {
+    Info,
+    Success,
+    Error,
+}
+
+// 同期や初期化の完了など、単発のメッセージ
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputMessage {
+    pub kind: MessageKind,
+    pub title: String,
+    pub detail: Option<String>,
+}
+
+impl OutputMessage {
+    pub fn success(title: impl Into<String>, detail: Option<String>) -> Self {
+        Self {
+            kind: MessageKind::Success,
+            title: title.into(),
+            detail,
+        }
+    }
+
+    pub fn info(title: impl Into<String>, detail: Option<String>) -> Self {
+        Self {
+            kind: MessageKind::Info,
+            title: title.into(),
+            detail,
+        }
+    }
+}
+
+// コマンドが失敗したときのエラー（code は Error::code、exit_code はプロセスの終了コード）
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputError {
+    pub code: String,
+    pub message: String,
+    pub exit_code: u8,
+    // Drive API のエラーのときの HTTP ステータスと理由（userRateLimitExceeded など）
+    pub status: Option<u16>,
+    pub reason: Option<String>,
+}
+
+pub trait Formatter {
+    fn format_items(&self, items: &[OutputItem]) -> String;
+    fn format_message(&self, message: &OutputMessage) -> String;
+    // エラーは常に標準エラー出力にも書くので、人が読む形式では空でよい
+    fn format_error(&self, error: &OutputError) -> String;
+
+    // 診断結果。JSON を読む利用側には状態や対処法をそのままの形で渡す
+    fn format_report(&self, report: &DoctorReport) -> String {
+        self.format_items(&report_items(report))
+    }
+}
+
+// 診断結果を、確認項目ごとの一覧（id は確認項目の id）にする
+pub fn report_items(report: &DoctorReport) -> Vec<OutputItem> {
+    report.checks.iter()
+        .map(|check| {
+            let label = match check.status {
+                CheckStatus::Pass => "OK",
+                CheckStatus::Warn => "注意",
+                CheckStatus::Fail => "失敗",
+            };
+            let subtitle = match &check.remedy {
+                Some(remedy) => format!("{} → {}", check.detail, remedy),
+                None => check.detail.clone(),
+            };
+            OutputItem {
+                id: Some(check.id.to_string()),
+                title: format!("[{}] {}", label, check.title),
+                subtitle,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+impl OutputFormat {
+    pub fn formatter(self) -> Box<dyn Formatter> {
+        match self {
+            OutputFormat::Alfred => Box::new(alfred::AlfredFormatter),
+            OutputFormat::Raycast => Box::new(raycast::RaycastFormatter),
+            OutputFormat::Text => Box::new(text::TextFormatter),
+            OutputFormat::Tsv => Box::new(tsv::TsvFormatter),
+            OutputFormat::Ndjson => Box::new(ndjson::NdjsonFormatter),
+        }
+    }
+
+    pub fn print_items(self, items: &[OutputItem]) {
+        print_output(self.formatter().format_items(items));
+    }
+
+    pub fn print_message(self, message: &OutputMessage) {
+        print_output(self.formatter().format_message(message));
+    }
+
+    pub fn print_error(self, error: &OutputError) {
+        print_output(self.formatter().format_error(error));
+    }
+
+    pub fn print_report(self, report: &DoctorReport) {
+        print_output(self.formatter().format_report(report));
+    }
+}
+
+// 出力先が閉じられていても（head などへのパイプ）パニックにしない
+pub fn print_output(mut output: String) {
+    if output.is_empty() {
+        return;
+    }
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+    let mut stdout = std::io::stdout().lock();
+    let _ = stdout.write_all(output.as_bytes()).and_then(|_| stdout.flush());
+}
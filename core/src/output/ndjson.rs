@@ -0,0 +1,53 @@
+// 1行に1つの JSON オブジェクト（type で項目とメッセージを区別する）
+
+use serde_json::{json, Value};
+
+use super::{Formatter, OutputError, OutputItem, OutputMessage};
+use crate::models::SyncProgress;
+use crate::services::DoctorReport;
+
+pub struct NdjsonFormatter;
+
+impl Formatter for NdjsonFormatter {
+    fn format_items(&self, items: &[OutputItem]) -> String {
+        items.iter()
+            .map(|item| {
+                let mut value = serde_json::to_value(item).unwrap_or(Value::Null);
+                value["type"] = json!("item");
+                format!("{}\n", value)
+            })
+            .collect()
+    }
+
+    fn format_message(&self, message: &OutputMessage) -> String {
+        let mut value = serde_json::to_value(message).unwrap_or(Value::Null);
+        value["type"] = json!("message");
+        format!("{}\n", value)
+    }
+
+    fn format_error(&self, error: &OutputError) -> String {
+        let mut value = serde_json::to_value(error).unwrap_or(Value::Null);
+        value["type"] = json!("error");
+        format!("{}\n", value)
+    }
+
+    // 確認項目ごとに1行（type: check）、最後に全体の状態（type: report）
+    fn format_report(&self, report: &DoctorReport) -> String {
+        let mut output: String = report.checks.iter()
+            .map(|check| {
+                let mut value = serde_json::to_value(check).unwrap_or(Value::Null);
+                value["type"] = json!("check");
+                format!("{}\n", value)
+            })
+            .collect();
+        output.push_str(&format!("{}\n", json!({ "type": "report", "status": report.status })));
+        output
+    }
+}
+
+// 同期の進捗（event で種類を区別する）
+pub fn format_progress(event: &SyncProgress) -> String {
+    let mut value = serde_json::to_value(event).unwrap_or(Value::Null);
+    value["type"] = json!("progress");
+    format!("{}\n", value)
+}
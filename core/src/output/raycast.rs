@@ -0,0 +1,48 @@
+// Raycast の拡張機能から扱いやすい JSON（キーは camelCase）
+
+use serde_json::{json, Value};
+
+use super::{Formatter, OutputError, OutputItem, OutputMessage};
+use crate::services::DoctorReport;
+
+pub struct RaycastFormatter;
+
+impl Formatter for RaycastFormatter {
+    fn format_items(&self, items: &[OutputItem]) -> String {
+        let items: Vec<Value> = items.iter()
+            .map(|item| json!({
+                "id": item.id,
+                "title": item.title,
+                "subtitle": item.subtitle,
+                "url": item.url,
+                "mimeType": item.mime_type,
+                "modifiedTime": item.modified_time,
+                "score": item.score,
+                "matchedRanges": item.matched_ranges,
+                "error": item.error,
+                "warning": item.warning,
+            }))
+            .collect();
+        serde_json::to_string(&json!({ "items": items })).unwrap_or_default()
+    }
+
+    fn format_message(&self, message: &OutputMessage) -> String {
+        serde_json::to_string(&json!({ "message": message })).unwrap_or_default()
+    }
+
+    fn format_error(&self, error: &OutputError) -> String {
+        serde_json::to_string(&json!({
+            "error": {
+                "code": error.code,
+                "message": error.message,
+                "exitCode": error.exit_code,
+                "status": error.status,
+                "reason": error.reason,
+            }
+        })).unwrap_or_default()
+    }
+
+    fn format_report(&self, report: &DoctorReport) -> String {
+        serde_json::to_string(&json!({ "report": report })).unwrap_or_default()
+    }
+}
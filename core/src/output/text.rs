@@ -0,0 +1,41 @@
+// 人が読むための出力
+
+use super::{Formatter, MessageKind, OutputError, OutputItem, OutputMessage};
+
+pub struct TextFormatter;
+
+impl Formatter for TextFormatter {
+    fn format_items(&self, items: &[OutputItem]) -> String {
+        if items.is_empty() {
+            return "該当する項目はありません\n".to_string();
+        }
+
+        let mut output = String::new();
+        for item in items {
+            output.push_str(&item.title);
+            output.push('\n');
+            if !item.subtitle.is_empty() {
+                output.push_str(&format!("  {}\n", item.subtitle));
+            }
+            if let Some(ref url) = item.url {
+                output.push_str(&format!("  {}\n", url));
+            }
+        }
+        output
+    }
+
+    fn format_message(&self, message: &OutputMessage) -> String {
+        let prefix = match message.kind {
+            MessageKind::Error => "エラー: ",
+            MessageKind::Info | MessageKind::Success => "",
+        };
+        match message.detail {
+            Some(ref detail) => format!("{}{}\n  {}\n", prefix, message.title, detail),
+            None => format!("{}{}\n", prefix, message.title),
+        }
+    }
+
+    fn format_error(&self, _error: &OutputError) -> String {
+        String::new()
+    }
+}
@@ -0,0 +1,47 @@
+// タブ区切り（1行目は見出し）。値に含まれるタブと改行は空白に置き換える
+
+use super::{Formatter, OutputError, OutputItem, OutputMessage};
+
+pub struct TsvFormatter;
+
+impl Formatter for TsvFormatter {
+    fn format_items(&self, items: &[OutputItem]) -> String {
+        let mut output = String::from("id\ttitle\tsubtitle\turl\tmime_type\tmodified_time\tscore\n");
+        for item in items {
+            let fields = [
+                item.id.clone().unwrap_or_default(),
+                item.title.clone(),
+                item.subtitle.clone(),
+                item.url.clone().unwrap_or_default(),
+                item.mime_type.clone().unwrap_or_default(),
+                item.modified_time.map(|time| time.to_rfc3339()).unwrap_or_default(),
+                item.score.map(|score| format!("{:.4}", score)).unwrap_or_default(),
+            ];
+            output.push_str(&fields.iter().map(|field| escape(field)).collect::<Vec<_>>().join("\t"));
+            output.push('\n');
+        }
+        output
+    }
+
+    fn format_message(&self, message: &OutputMessage) -> String {
+        let kind = serde_json::to_value(message.kind)
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_default();
+        format!(
+            "kind\ttitle\tdetail\n{}\t{}\t{}\n",
+            kind,
+            escape(&message.title),
+            escape(message.detail.as_deref().unwrap_or_default())
+        )
+    }
+
+    // 見出しの異なる表を混ぜないよう、エラーは標準エラー出力だけに書く
+    fn format_error(&self, _error: &OutputError) -> String {
+        String::new()
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
@@ -0,0 +1,424 @@
+// 設定・認証・保存データ・Drive API への接続を順に確認し、問題と対処法を報告する
+// 「検索結果が出ない」ときに、どの段階で止まっているかを切り分けるために使う
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::fs;
+use tokio::sync::Semaphore;
+
+use crate::infra::{
+    open_index_store, AppConfig, AuthenticatedClient, ConfigManager, GoogleDriveClient, TokenInfo, FOLDER_MIME_TYPE,
+};
+
+// 最終同期からこれ以上経っていたら注意を出す
+const STALE_SYNC_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    // 確認項目の識別子（config_file, credentials など）
+    pub id: &'static str,
+    pub title: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    // 問題がある場合の対処法
+    pub remedy: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub status: CheckStatus,
+    pub checks: Vec<CheckResult>,
+}
+
+impl CheckResult {
+    fn pass(id: &'static str, title: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            id,
+            title,
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            remedy: None,
+        }
+    }
+
+    fn warn(id: &'static str, title: &'static str, detail: impl Into<String>, remedy: impl Into<String>) -> Self {
+        Self {
+            id,
+            title,
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            remedy: Some(remedy.into()),
+        }
+    }
+
+    fn fail(id: &'static str, title: &'static str, detail: impl Into<String>, remedy: impl Into<String>) -> Self {
+        Self {
+            id,
+            title,
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            remedy: Some(remedy.into()),
+        }
+    }
+}
+
+impl DoctorReport {
+    fn new(checks: Vec<CheckResult>) -> Self {
+        let status = checks.iter().map(|check| check.status).max().unwrap_or(CheckStatus::Pass);
+        Self { status, checks }
+    }
+
+    // 失敗した項目があるか（終了コードの判断に使う）
+    pub fn has_failures(&self) -> bool {
+        self.status == CheckStatus::Fail
+    }
+}
+
+pub struct DoctorService {
+    config_manager: ConfigManager,
+}
+
+impl DoctorService {
+    pub fn new(config_manager: ConfigManager) -> Self {
+        Self { config_manager }
+    }
+
+    pub async fn run(&self) -> DoctorReport {
+        let mut checks = Vec::new();
+
+        let config = self.check_config(&mut checks);
+        let tokens = self.check_tokens(&mut checks);
+
+        // API の確認には認証情報とトークンの両方が必要
+        match (&config, tokens) {
+            (Some(config), Some(tokens)) => {
+                if let Some(drive_client) = self.check_connection(config, tokens, &mut checks).await {
+                    checks.push(Self::check_folders(config, &drive_client).await);
+                }
+            }
+            _ => checks.push(CheckResult::warn(
+                "connection",
+                "Drive API への接続",
+                "設定または認証に問題があるため確認できません",
+                "上の項目を解決してから、もう一度 doctor を実行してください",
+            )),
+        }
+
+        if let Some(config) = &config {
+            checks.extend(self.check_storage(config, Utc::now()));
+        }
+
+        DoctorReport::new(checks)
+    }
+
+    // 設定ファイルの読み込み、認証情報、検索対象フォルダを確認する
+    fn check_config(&self, checks: &mut Vec<CheckResult>) -> Option<AppConfig> {
+        let config_path = self.config_manager.config_dir.join("config.toml");
+        let config_path_text = config_path.display().to_string();
+
+        let content = match fs::read_to_string(&config_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                checks.push(CheckResult::fail(
+                    "config_file",
+                    "設定ファイル",
+                    format!("{} がありません", config_path_text),
+                    "fuzzy-drive-search init を実行して設定ファイルを作成してください",
+                ));
+                return None;
+            }
+            Err(e) => {
+                checks.push(CheckResult::fail(
+                    "config_file",
+                    "設定ファイル",
+                    format!("{} を読み込めません: {}", config_path_text, e),
+                    "ファイルの権限を確認してください",
+                ));
+                return None;
+            }
+        };
+
+        let config: AppConfig = match toml::from_str(&content) {
+            Ok(config) => config,
+            Err(e) => {
+                checks.push(CheckResult::fail(
+                    "config_file",
+                    "設定ファイル",
+                    format!("{} の形式が正しくありません: {}", config_path_text, e),
+                    "設定ファイルを修正するか、削除して fuzzy-drive-search init をやり直してください",
+                ));
+                return None;
+            }
+        };
+        checks.push(CheckResult::pass("config_file", "設定ファイル", config_path_text));
+
+        if config.google_client_id == AppConfig::default().google_client_id
+            || config.google_client_secret == AppConfig::default().google_client_secret
+        {
+            checks.push(CheckResult::fail(
+                "credentials",
+                "OAuth クライアント",
+                "Client ID / Client Secret が初期値のままです",
+                "fuzzy-drive-search config set client-id <ID> と config set client-secret <SECRET> で設定してください",
+            ));
+        } else {
+            checks.push(CheckResult::pass("credentials", "OAuth クライアント", "設定済み"));
+        }
+
+        if config.target_folder_ids.is_empty() {
+            checks.push(CheckResult::fail(
+                "target_folders",
+                "検索対象フォルダ",
+                "検索対象フォルダが設定されていません",
+                "fuzzy-drive-search folders add <フォルダのURL> で追加してください",
+            ));
+        } else {
+            checks.push(CheckResult::pass(
+                "target_folders",
+                "検索対象フォルダ",
+                format!("{}個", config.target_folder_ids.len()),
+            ));
+        }
+
+        Some(config)
+    }
+
+    fn check_tokens(&self, checks: &mut Vec<CheckResult>) -> Option<TokenInfo> {
+        match self.config_manager.load_tokens() {
+            Ok(Some(tokens)) => {
+                if tokens.refresh_token.is_some() {
+                    let detail = match tokens.expires_at {
+                        Some(expires_at) if expires_at > Utc::now() => format!(
+                            "保存済み（{}まで有効）",
+                            expires_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                        ),
+                        _ => "保存済み（期限切れのため、使うときに自動で更新します）".to_string(),
+                    };
+                    checks.push(CheckResult::pass("tokens", "認証トークン", detail));
+                } else {
+                    checks.push(CheckResult::warn(
+                        "tokens",
+                        "認証トークン",
+                        "リフレッシュトークンがないため、期限が切れると再認証が必要です",
+                        "fuzzy-drive-search auth login --force で認証し直してください",
+                    ));
+                }
+                Some(tokens)
+            }
+            Ok(None) => {
+                checks.push(CheckResult::fail(
+                    "tokens",
+                    "認証トークン",
+                    "認証されていません",
+                    "fuzzy-drive-search auth login で認証してください",
+                ));
+                None
+            }
+            Err(e) => {
+                checks.push(CheckResult::fail(
+                    "tokens",
+                    "認証トークン",
+                    format!("tokens.json を読み込めません: {}", e),
+                    "fuzzy-drive-search auth logout の後、auth login で認証し直してください",
+                ));
+                None
+            }
+        }
+    }
+
+    // 保存済みのトークンで接続する（期限切れや拒否された場合はクライアントが更新する）
+    // 接続できれば、以降の確認に使うクライアントを返す
+    async fn check_connection(&self, config: &AppConfig, tokens: TokenInfo, checks: &mut Vec<CheckResult>) -> Option<GoogleDriveClient> {
+        let original_access_token = tokens.access_token.clone();
+        let drive_client = GoogleDriveClient::new(
+            AuthenticatedClient::new(self.config_manager.clone(), config, tokens),
+        );
+
+        match drive_client.test_connection().await {
+            Ok(true) => {
+                let refreshed = drive_client.http().tokens().await.access_token != original_access_token;
+                checks.push(CheckResult::pass(
+                    "connection",
+                    "Drive API への接続",
+                    if refreshed { "期限切れのトークンを更新して接続できました" } else { "接続できました" },
+                ));
+                Some(drive_client)
+            }
+            Ok(false) => {
+                checks.push(CheckResult::fail(
+                    "connection",
+                    "Drive API への接続",
+                    "Drive API がリクエストを拒否しました",
+                    "Google Cloud Console で Drive API が有効か確認し、auth login --force で認証し直してください",
+                ));
+                None
+            }
+            Err(e) => {
+                checks.push(CheckResult::fail(
+                    "connection",
+                    "Drive API への接続",
+                    format!("{:#}", e),
+                    "ネットワーク接続（プロキシ・VPN を含む）を確認するか、auth login --force で認証し直してください",
+                ));
+                None
+            }
+        }
+    }
+
+    // 検索対象フォルダごとに、フォルダとして取得できるかを確認する
+    async fn check_folders(config: &AppConfig, drive_client: &GoogleDriveClient) -> CheckResult {
+        let mut accessible = Vec::new();
+        let mut problems = Vec::new();
+
+        let requests = Semaphore::new(config.sync_concurrency());
+        let folder_infos = drive_client.get_folder_infos(&config.target_folder_ids, &requests).await;
+        for (folder_id, result) in folder_infos {
+            match result {
+                Ok(folder_info) if folder_info.mime_type == FOLDER_MIME_TYPE => accessible.push(folder_info.name),
+                Ok(folder_info) => problems.push(format!("{}（フォルダではありません）", folder_info.name)),
+                Err(_) => problems.push(format!("{}（取得できません）", folder_id)),
+            }
+        }
+
+        if problems.is_empty() {
+            CheckResult::pass("folder_access", "フォルダへのアクセス", accessible.join(", "))
+        } else {
+            CheckResult::fail(
+                "folder_access",
+                "フォルダへのアクセス",
+                problems.join(", "),
+                "フォルダIDと共有設定を確認し、fuzzy-drive-search folders remove で不要なフォルダを外してください",
+            )
+        }
+    }
+
+    // 保存データを読み込めるか、件数と最終同期日時を確認する
+    fn check_storage(&self, config: &AppConfig, now: DateTime<Utc>) -> Vec<CheckResult> {
+        let index_store = match open_index_store(&self.config_manager.config_dir, config.storage_backend) {
+            Ok(index_store) => index_store,
+            Err(e) => {
+                return vec![CheckResult::fail(
+                    "storage",
+                    "保存データ",
+                    format!("ストレージを開けません: {}", e),
+                    "fuzzy-drive-search config set storage-backend json で JSON 形式に戻してください",
+                )];
+            }
+        };
+
+        let data = match index_store.load_data() {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                return vec![CheckResult::warn(
+                    "storage",
+                    "保存データ",
+                    "まだ同期されていません",
+                    "fuzzy-drive-search sync を実行してください",
+                )];
+            }
+            Err(e) => {
+                return vec![CheckResult::fail(
+                    "storage",
+                    "保存データ",
+                    format!("保存データを読み込めません: {:#}", e),
+                    "fuzzy-drive-search sync を実行して作り直してください",
+                )];
+            }
+        };
+
+        let storage = if data.files.is_empty() {
+            CheckResult::warn(
+                "storage",
+                "保存データ",
+                "同期済みのファイルが0件です",
+                "検索対象フォルダにファイルがあるか確認し、fuzzy-drive-search sync を実行してください",
+            )
+        } else {
+            CheckResult::pass(
+                "storage",
+                "保存データ",
+                format!("{}件のファイル、{}個のフォルダ", data.files.len(), data.folders.len()),
+            )
+        };
+
+        vec![storage, Self::check_last_sync(data.last_sync, now)]
+    }
+
+    fn check_last_sync(last_sync: DateTime<Utc>, now: DateTime<Utc>) -> CheckResult {
+        let last_sync_text = last_sync.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string();
+        let age = now - last_sync;
+        if age > Duration::hours(STALE_SYNC_HOURS) {
+            CheckResult::warn(
+                "last_sync",
+                "最終同期",
+                format!("{}（{}日前）", last_sync_text, age.num_days()),
+                "fuzzy-drive-search sync を実行してください（定期同期が動いているかも確認してください）",
+            )
+        } else {
+            CheckResult::pass("last_sync", "最終同期", last_sync_text)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn statuses(checks: &[CheckResult]) -> Vec<(&'static str, CheckStatus)> {
+        checks.iter().map(|check| (check.id, check.status)).collect()
+    }
+
+    #[test]
+    fn reports_placeholder_config_and_corrupt_storage() {
+        let dir = temp_dir();
+        let doctor = DoctorService::new(ConfigManager::with_config_dir(dir.path().to_path_buf()).unwrap());
+        let config_dir = doctor.config_manager.config_dir.clone();
+
+        let mut checks = Vec::new();
+        assert!(doctor.check_config(&mut checks).is_none());
+        assert_eq!(statuses(&checks), vec![("config_file", CheckStatus::Fail)]);
+
+        doctor.config_manager.save_config(&AppConfig::default()).unwrap();
+        let mut checks = Vec::new();
+        let config = doctor.check_config(&mut checks).unwrap();
+        assert_eq!(
+            statuses(&checks),
+            vec![
+                ("config_file", CheckStatus::Pass),
+                ("credentials", CheckStatus::Fail),
+                ("target_folders", CheckStatus::Fail),
+            ]
+        );
+
+        let checks = doctor.check_storage(&config, Utc::now());
+        assert_eq!(statuses(&checks), vec![("storage", CheckStatus::Warn)]);
+
+        fs::write(config_dir.join("drive_files.json"), "{ broken").unwrap();
+        let checks = doctor.check_storage(&config, Utc::now());
+        assert_eq!(statuses(&checks), vec![("storage", CheckStatus::Fail)]);
+        assert!(checks[0].remedy.is_some());
+    }
+
+    #[test]
+    fn warns_about_stale_sync() {
+        let now = Utc::now();
+        assert_eq!(DoctorService::check_last_sync(now - Duration::hours(1), now).status, CheckStatus::Pass);
+        assert_eq!(DoctorService::check_last_sync(now - Duration::days(3), now).status, CheckStatus::Warn);
+
+        let report = DoctorReport::new(vec![
+            CheckResult::pass("a", "a", ""),
+            CheckResult::warn("b", "b", "", ""),
+        ]);
+        assert_eq!(report.status, CheckStatus::Warn);
+        assert!(!report.has_failures());
+    }
+}
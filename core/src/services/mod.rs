@@ -1,3 +1,5 @@
+pub mod doctor;
 pub mod search;
 
+pub use doctor::*;
 pub use search::*;
\ No newline at end of file
@@ -1,26 +1,88 @@
-use anyhow::Result;
-use chrono::{Duration, Utc};
+pub mod matcher;
+pub mod query;
+
+use crate::error::{Error, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
 
 use crate::infra::{
-    ConfigManager, JsonStorage, GoogleDriveClient, OAuth2Client,
+    build_breadcrumb, open_index_store, AppConfig, AuthenticatedClient, ConfigManager, DriveApiError,
+    DriveApiFile, DriveChange, FileLock, FolderCrawlRoot, FolderSettings, GoogleDriveClient, HistoryStore,
+    IndexStore, JsonStorageData, JsonStorageFile, OpenEvent, StorageBackend,
 };
+use crate::models::{DriveFile, ProgressSender, SearchResult, SyncMode, SyncProgress};
+use crate::text::normalize::{map_range_to_original, normalize, normalize_with_map_keep_case};
+use crate::text::romaji::canonical_romaji;
+use matcher::{match_text, merge_ranges, TextMatch};
+use query::{parse_query, Query, QueryTerm, TermKind};
+
+// ファイル名以外での一致はファイル名での一致より低く評価する
+const KEYWORD_WEIGHT: f64 = 0.9;
+const ROMAJI_WEIGHT: f64 = 0.8;
+const FOLDER_WEIGHT: f64 = 0.5;
+// よく・最近開いたファイルに加えるスコアの上限と、上限に近づく速さ（frecency がこの値で約63%）
+const FRECENCY_WEIGHT: f64 = 0.5;
+const FRECENCY_SATURATION: f64 = 5.0;
+
+// 検索語を正規化したもの（フィルタ以外の語と、フォルダ名・所有者の値）
+struct SearchTerm<'a> {
+    term: &'a QueryTerm,
+    text: String,
+}
+
+impl SearchTerm<'_> {
+    fn is_text(&self) -> bool {
+        matches!(self.term.kind, TermKind::Word(_) | TermKind::Phrase(_))
+    }
+}
+
+// 照合に使う、ファイルごとの正規化済みの文字列
+struct NormalizedTexts {
+    // 小文字化したもの（部分文字列として含むかの判定用）
+    name: String,
+    // 大文字小文字を残したもの（camelCase の区切りを採点に使う）
+    cased_name: String,
+    breadcrumb: String,
+}
+
+// 同期済みの索引と設定の状態
+#[derive(Debug, Clone)]
+pub struct ServiceStatus {
+    pub config_dir: PathBuf,
+    pub storage_backend: StorageBackend,
+    pub target_folder_count: usize,
+    pub file_count: usize,
+    pub last_sync: Option<DateTime<Utc>>,
+    pub authenticated: bool,
+    pub history_file_count: usize,
+}
 
 pub struct SearchService {
     config_manager: ConfigManager,
-    json_storage: JsonStorage,
+    index_store: Box<dyn IndexStore>,
+    history: HistoryStore,
 }
 
 impl SearchService {
     pub fn new() -> Result<Self> {
-        let config_manager = ConfigManager::new()?;
+        Self::with_config_manager(ConfigManager::new()?)
+    }
+
+    pub fn with_config_manager(config_manager: ConfigManager) -> Result<Self> {
         
-        // JSONストレージのパスを設定
-        let storage_path = config_manager.config_dir.join("drive_files.json");
-        let json_storage = JsonStorage::new(storage_path)?;
+        // 設定された形式のストレージを開く
+        let config = config_manager.load_config()?;
+        let index_store = open_index_store(&config_manager.config_dir, config.storage_backend)?;
         
+        let history = HistoryStore::new(&config_manager.config_dir);
+
         Ok(Self {
             config_manager,
-            json_storage,
+            index_store,
+            history,
         })
     }
 
@@ -42,125 +104,532 @@ impl SearchService {
         // 認証トークンの確認・取得
         self.ensure_authenticated(&config).await?;
         
-        // JSONストレージの初期化確認
-        let file_count = self.json_storage.get_file_count()?;
+        // ストレージの初期化確認
+        let file_count = self.index_store.get_file_count()?;
         if file_count == 0 {
-            println!("初回同期を実行します...");
+            info!("初回同期を実行します...");
             self.sync_files().await?;
         } else {
-            println!("ストレージに{}件のファイルがあります", file_count);
+            info!("ストレージに{}件のファイルがあります", file_count);
         }
 
         Ok(())
     }
 
-    async fn ensure_authenticated(&self, config: &crate::infra::AppConfig) -> Result<()> {
-        let oauth_client = OAuth2Client::new(
-            config.google_client_id.clone(),
-            config.google_client_secret.clone(),
-        );
+    pub fn config_manager(&self) -> &ConfigManager {
+        &self.config_manager
+    }
+
+    // force なら保存済みのトークンに関わらず、ブラウザでの認証をやり直す
+    pub async fn login(&self, force: bool) -> Result<()> {
+        let config = self.config_manager.load_config()?;
+        if !force {
+            return self.ensure_authenticated(&config).await;
+        }
+
+        let oauth_client = config.oauth_client();
+        let tokens = oauth_client.authorize().await?;
+        self.config_manager.save_tokens(&tokens)?;
+        info!("認証が完了しました");
+        Ok(())
+    }
+
+    // 保存済みのトークンがあれば使い、期限切れならリフレッシュトークンで更新する
+    // 更新できない・トークンが無い場合はブラウザで認証する
+    async fn ensure_authenticated(&self, config: &AppConfig) -> Result<()> {
+        let oauth_client = config.oauth_client();
 
-        // 既存のトークンを確認
         if let Some(tokens) = self.config_manager.load_tokens()? {
-            // トークンの有効性を確認（簡易版）
-            let drive_client = GoogleDriveClient::new(tokens.access_token.clone());
-            
-            if drive_client.test_connection().await.unwrap_or(false) {
-                println!("既存の認証トークンが有効です");
+            if !tokens.needs_refresh(Utc::now()) {
+                debug!("既存の認証トークンを使用します");
                 return Ok(());
             }
 
-            // リフレッシュトークンで更新を試行
             if let Some(ref refresh_token) = tokens.refresh_token {
-                if let Ok(new_tokens) = oauth_client.refresh_token(refresh_token).await {
-                    println!("認証トークンを更新しました");
-                    self.config_manager.save_tokens(&new_tokens)?;
-                    return Ok(());
+                match oauth_client.refresh_token(refresh_token).await {
+                    Ok(new_tokens) => {
+                        info!("認証トークンを更新しました");
+                        self.config_manager.save_tokens(&new_tokens)?;
+                        return Ok(());
+                    }
+                    Err(e) => warn!("認証トークンを更新できませんでした: {:#}", e),
                 }
             }
         }
 
         // 新規認証
-        println!("認証が必要です。OAuth2フローを開始します...");
+        info!("認証が必要です。OAuth2フローを開始します...");
         let tokens = oauth_client.authorize().await?;
         self.config_manager.save_tokens(&tokens)?;
-        println!("認証が完了しました");
+        info!("認証が完了しました");
 
         Ok(())
     }
 
     pub async fn sync_files(&mut self) -> Result<()> {
+        self.sync_files_with_progress(ProgressSender::disabled()).await
+    }
+
+    // 同期の進み具合を progress に送りながら同期する。終了すると progress は破棄され、受け手の待機も終わる
+    // 同期を続けられない失敗は進捗ではなく戻り値で返す
+    pub async fn sync_files_with_progress(&mut self, progress: ProgressSender) -> Result<()> {
+        match FileLock::try_acquire(&self.sync_lock_path()) {
+            Ok(Some(_lock)) => self.sync_files_locked(&progress).await,
+            Ok(None) => Err(Error::SyncInProgress),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // 同期処理の多重実行を防ぐロックファイル
+    fn sync_lock_path(&self) -> PathBuf {
+        self.config_manager.config_dir.join("sync.lock")
+    }
+
+    // 同期ロックを取得済みの状態で呼び出す
+    async fn sync_files_locked(&mut self, progress: &ProgressSender) -> Result<()> {
         let config = self.config_manager.load_config()?;
         if config.target_folder_ids.is_empty() {
-            return Err(anyhow::anyhow!("検索対象フォルダIDが設定されていません"));
+            return Err(Error::ConfigMissing("検索対象フォルダIDが設定されていません".to_string()));
         }
 
         let tokens = self.config_manager.load_tokens()?
-            .ok_or_else(|| anyhow::anyhow!("認証トークンが見つかりません"))?;
+            .ok_or_else(|| Error::AuthRequired("認証トークンが見つかりません".to_string()))?;
 
-        let drive_client = GoogleDriveClient::new(tokens.access_token);
+        let drive_client = GoogleDriveClient::new(
+            AuthenticatedClient::new(self.config_manager.clone(), &config, tokens),
+        );
 
-        println!("Google Driveから{}個のフォルダの直下ファイルを取得中...", config.target_folder_ids.len());
-        
-        // 各フォルダ情報を確認
-        for folder_id in &config.target_folder_ids {
-            println!("\nフォルダID {} の情報を確認中...", folder_id);
-            if let Err(e) = drive_client.get_folder_info(folder_id).await {
-                println!("フォルダ情報取得エラー: {}", e);
+        // 保存済みのトークンがあり、対象フォルダと取得設定が前回と同じなら差分同期を試みる
+        if let Some(data) = self.index_store.load_data()? {
+            if let Some(page_token) = data.sync_token.clone() {
+                if data.folder_settings == Self::effective_folder_settings(&config) {
+                    progress.send(SyncProgress::SyncStarted {
+                        mode: SyncMode::Incremental,
+                        folder_count: config.target_folder_ids.len(),
+                    });
+                    // 全件同期で回復できる失敗（トークンの期限切れ・フォルダ構成の変更）以外はそのまま返す
+                    match self.sync_incremental(&drive_client, &config, data, &page_token, progress).await {
+                        Ok(()) => return Ok(()),
+                        Err(e) => {
+                            let reason = full_sync_reason(e)?;
+                            info!("差分同期では反映できないため全件同期を行います: {}", reason);
+                        }
+                    }
+                } else {
+                    info!("検索対象フォルダの設定が変更されたため全件同期を行います");
+                }
             }
         }
 
-        // 複数フォルダの直下ファイルのみを取得
-        let api_files = drive_client.list_files_in_folders_directly(&config.target_folder_ids).await?;
+        self.sync_full(&drive_client, &config, progress).await
+    }
+
+    async fn sync_full(&mut self, drive_client: &GoogleDriveClient, config: &AppConfig, progress: &ProgressSender) -> Result<()> {
+        progress.send(SyncProgress::SyncStarted {
+            mode: SyncMode::Full,
+            folder_count: config.target_folder_ids.len(),
+        });
+        // 一覧取得中の変更も次回の差分同期で拾えるよう、先にトークンを取得しておく
+        let start_page_token = drive_client.get_start_page_token().await?;
+
+        // 各フォルダのファイルを取得（設定されたフォルダはサブフォルダも辿る）
+        let roots: Vec<FolderCrawlRoot> = config.target_folder_ids.iter().map(|folder_id| {
+            let settings = config.folder_settings_for(folder_id);
+            FolderCrawlRoot {
+                folder_id: folder_id.clone(),
+                recursive: settings.recursive,
+                max_depth: settings.max_depth,
+            }
+        }).collect();
+        // 対象フォルダの名前の取得はファイル一覧の取得と並行して行い、同時リクエスト数の上限は両方で共有する
+        let requests = Semaphore::new(config.sync_concurrency());
+        let (crawl, root_names) = tokio::join!(
+            drive_client.list_files_in_folders(&roots, &requests, progress),
+            Self::fetch_folder_names_for_sync(drive_client, &config.target_folder_ids, &requests, progress),
+        );
+        let crawl = crawl?;
         
         let mut all_files = Vec::new();
-        for api_file in api_files {
-            let file = crate::models::DriveFile::new(
-                api_file.id,
-                api_file.name,
-                api_file.web_view_link.unwrap_or_default(),
-                chrono::DateTime::parse_from_rfc3339(&api_file.modified_time)?
-                    .with_timezone(&Utc),
-                api_file.mime_type,
-                api_file.parents.unwrap_or_default(),
-            );
-            all_files.push(file);
-        }
-
-        // フォルダ名を取得
-        let folder_names = self.fetch_folder_names_for_sync(&drive_client, &config.target_folder_ids).await?;
+        for api_file in crawl.files {
+            all_files.push(api_file.into_drive_file()?);
+        }
+
+        // フォルダ名（辿ったサブフォルダの名前に、対象フォルダの名前を加える）
+        let mut folder_names = crawl.folder_names;
+        folder_names.extend(root_names);
         
-        // JSONストレージに保存
-        self.json_storage.save_data(&all_files, &folder_names, None)?;
+        // ストレージに保存
+        self.index_store.save_data(
+            &all_files,
+            &folder_names,
+            &crawl.folder_paths,
+            &Self::effective_folder_settings(config),
+            Some(start_page_token),
+        )?;
+        progress.send(SyncProgress::StorageWritten { file_count: all_files.len() });
 
-        println!("同期が完了しました。{}件のファイルを取得しました", all_files.len());
+        info!("同期が完了しました。{}件のファイルを取得しました", all_files.len());
         Ok(())
     }
 
+    async fn sync_incremental(
+        &mut self,
+        drive_client: &GoogleDriveClient,
+        config: &AppConfig,
+        data: JsonStorageData,
+        page_token: &str,
+        progress: &ProgressSender,
+    ) -> anyhow::Result<()> {
+        info!("前回の同期以降の変更を取得中...");
+        let (changes, new_page_token) = drive_client.list_all_changes(page_token).await
+            .map_err(|e| match FullSyncReason::from_changes_error(&e) {
+                Some(reason) => reason.into(),
+                None => e,
+            })?;
+        progress.send(SyncProgress::ChangesFetched { change_count: changes.len() });
+
+        let change_count = changes.len();
+        let folder_settings = Self::effective_folder_settings(config);
+        let applied = apply_changes(config, data, changes)?;
 
-    pub fn get_folder_names(&self) -> Result<std::collections::HashMap<String, String>> {
-        self.json_storage.get_folder_names()
+        self.index_store.save_data(
+            &applied.files,
+            &applied.folder_names,
+            &applied.folder_paths,
+            &folder_settings,
+            Some(new_page_token),
+        )?;
+        progress.send(SyncProgress::StorageWritten { file_count: applied.files.len() });
+
+        info!("差分同期が完了しました。{}件の変更を反映しました（合計{}件）", change_count, applied.files.len());
+        Ok(())
     }
 
-    async fn fetch_folder_names_for_sync(&self, drive_client: &GoogleDriveClient, folder_ids: &[String]) -> Result<std::collections::HashMap<String, String>> {
-        let mut folder_names = std::collections::HashMap::new();
-        
-        for folder_id in folder_ids {
-            if let Ok(folder_info) = drive_client.get_folder_info(folder_id).await {
-                if let Ok(folder_data) = serde_json::from_str::<serde_json::Value>(&folder_info) {
-                    if let Some(name) = folder_data["name"].as_str() {
-                        folder_names.insert(folder_id.clone(), name.to_string());
+    fn effective_folder_settings(config: &AppConfig) -> HashMap<String, FolderSettings> {
+        config.target_folder_ids.iter()
+            .map(|folder_id| (folder_id.clone(), config.folder_settings_for(folder_id)))
+            .collect()
+    }
+
+    // クエリの構文が不正な場合は Error::InvalidQuery を返す（位置つきで表示できるように）
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        self.search_parsed(&parse_query(query)?, limit)
+    }
+
+    // 解析済みのクエリで検索する（クエリの注意を表示したい呼び出し側向け）
+    pub fn search_parsed(&self, query: &Query, limit: usize) -> Result<Vec<SearchResult>> {
+        let terms = Self::search_terms(&query.terms);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // 全文検索での絞り込みには除外でない語だけを使う（フィルタのみなら全件が対象）
+        let words: Vec<&str> = terms.iter()
+            .filter(|term| !term.term.negated && term.is_text())
+            .map(|term| term.text.as_str())
+            .collect();
+
+        let folder_names: HashMap<String, String> = if terms.iter().any(|term| matches!(term.term.kind, TermKind::Folder(_))) {
+            self.index_store.get_folder_names()?
+                .into_iter()
+                .map(|(folder_id, name)| (folder_id, normalize(&name)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        let mut results: Vec<SearchResult> = self.index_store.search_candidates(&words)?
+            .into_iter()
+            .filter_map(|file| Self::score_file(file, &terms, &folder_names))
+            .collect();
+
+        // 一致の度合いに、開いた頻度と新しさを加味する（履歴が読めなくても検索は続ける）
+        let history = self.history.load().unwrap_or_default();
+        let now = Utc::now();
+        for result in &mut results {
+            let frecency = history.frecency(&result.file.id, now);
+            result.score += FRECENCY_WEIGHT * (1.0 - (-frecency / FRECENCY_SATURATION).exp());
+        }
+
+        results.sort_by(|a, b| {
+            b.score.partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b.file.modified_time.cmp(&a.file.modified_time))
+        });
+        results.truncate(limit);
+
+        Ok(results)
+    }
+
+    // 全角・半角やカタカナ・ひらがなの違いを吸収するため、索引と同じ正規化をかける
+    fn search_terms(terms: &[QueryTerm]) -> Vec<SearchTerm<'_>> {
+        terms.iter()
+            .map(|term| SearchTerm {
+                term,
+                text: match term.kind {
+                    TermKind::Word(ref text)
+                    | TermKind::Phrase(ref text)
+                    | TermKind::Folder(ref text)
+                    | TermKind::Owner(ref text) => normalize(text),
+                    _ => String::new(),
+                },
+            })
+            .filter(|term| !term.text.trim().is_empty() || !term.is_text())
+            .collect()
+    }
+
+    // すべての条件を満たすファイルにスコアを付ける（folder_names は正規化済みのフォルダ名）
+    fn score_file(
+        file: JsonStorageFile,
+        terms: &[SearchTerm],
+        folder_names: &HashMap<String, String>,
+    ) -> Option<SearchResult> {
+        let mut total_score = 0.0;
+        let mut scored_terms = 0;
+        let mut matched_ranges = Vec::new();
+
+        let (cased_name, name_origins) = normalize_with_map_keep_case(&file.name);
+        let texts = NormalizedTexts {
+            name: if file.normalized_name.is_empty() {
+                normalize(&file.name)
+            } else {
+                file.normalized_name.clone()
+            },
+            cased_name,
+            breadcrumb: normalize(&file.folder_breadcrumb),
+        };
+
+        for term in terms {
+            // 除外する語は、あいまい一致ではなく部分文字列として含むかで判定する
+            if term.term.negated {
+                let excluded = match term.term.kind {
+                    TermKind::Word(_) | TermKind::Phrase(_) => {
+                        Self::phrase_score(&term.text, &file, &texts).is_some()
+                    }
+                    _ => Self::matches_filter(term, &file, folder_names),
+                };
+                if excluded {
+                    return None;
+                }
+                continue;
+            }
+
+            let (term_score, name_match) = match term.term.kind {
+                TermKind::Word(_) => Self::word_score(&term.text, &file, &texts)?,
+                TermKind::Phrase(_) => Self::phrase_score(&term.text, &file, &texts)?,
+                _ => {
+                    if !Self::matches_filter(term, &file, folder_names) {
+                        return None;
                     }
+                    continue;
                 }
+            };
+
+            if let Some(name_match) = name_match {
+                matched_ranges.extend(name_match.ranges);
             }
+            total_score += term_score;
+            scored_terms += 1;
         }
-        
-        Ok(folder_names)
+
+        // 一致範囲は正規化後の位置なので、元のファイル名での位置に戻す
+        if !matched_ranges.is_empty() {
+            let name_len = file.name.chars().count();
+            matched_ranges = matched_ranges.into_iter()
+                .map(|range| map_range_to_original(&name_origins, name_len, range))
+                .collect();
+        }
+
+        // フィルタのみのクエリでは全件が同じスコアになり、更新日時順に並ぶ
+        let score = if scored_terms == 0 { 0.0 } else { total_score / scored_terms as f64 };
+
+        Some(SearchResult {
+            file: file.into_drive_file(),
+            score,
+            matched_ranges: merge_ranges(matched_ranges),
+        })
+    }
+
+    // 単語のあいまい一致のスコアと、ファイル名での一致
+    fn word_score(word: &str, file: &JsonStorageFile, texts: &NormalizedTexts) -> Option<(f64, Option<TextMatch>)> {
+        let name_match = match_text(word, &texts.cased_name);
+        let keyword_score = file.keywords.iter()
+            .filter_map(|keyword| match_text(word, keyword))
+            .map(|m| m.score * KEYWORD_WEIGHT)
+            .fold(0.0, f64::max);
+        let canonical_word = word.chars().any(|c| c.is_ascii_alphabetic())
+            .then(|| canonical_romaji(word));
+        let romaji_score = file.romaji_keywords.iter()
+            .filter_map(|romaji| match_text(word, romaji))
+            .chain(canonical_word.iter().flat_map(|canonical_word| {
+                file.romaji_canonical.iter()
+                    .filter_map(move |canonical| match_text(canonical_word, canonical))
+            }))
+            .map(|m| m.score * ROMAJI_WEIGHT)
+            .fold(0.0, f64::max);
+
+        let folder_score = match_text(word, &texts.breadcrumb)
+            .map(|m| m.score * FOLDER_WEIGHT)
+            .unwrap_or(0.0);
+
+        let name_score = name_match.as_ref().map(|m| m.score).unwrap_or(0.0);
+        let score = name_score.max(keyword_score).max(romaji_score).max(folder_score);
+        (score > 0.0).then_some((score, name_match))
+    }
+
+    // 語句をそのままの並びで含む場合のみ一致とする
+    fn phrase_score(phrase: &str, file: &JsonStorageFile, texts: &NormalizedTexts) -> Option<(f64, Option<TextMatch>)> {
+        if texts.name.contains(phrase) {
+            let name_match = match_text(phrase, &texts.cased_name);
+            return Some((name_match.as_ref().map(|m| m.score).unwrap_or(1.0), name_match));
+        }
+        if file.keywords.iter().any(|keyword| keyword.contains(phrase)) {
+            return Some((KEYWORD_WEIGHT, None));
+        }
+        let canonical_phrase = canonical_romaji(phrase);
+        if file.romaji_keywords.iter().any(|romaji| romaji.contains(phrase))
+            || file.romaji_canonical.iter().any(|canonical| canonical.contains(&canonical_phrase))
+        {
+            return Some((ROMAJI_WEIGHT, None));
+        }
+        if texts.breadcrumb.contains(phrase) {
+            return Some((FOLDER_WEIGHT, None));
+        }
+        None
+    }
+
+    fn matches_filter(term: &SearchTerm, file: &JsonStorageFile, folder_names: &HashMap<String, String>) -> bool {
+        match term.term.kind {
+            TermKind::Type(ref file_type) => file_type.matches(&file.mime_type),
+            TermKind::Modified(ref date_filter) => date_filter.matches(&file.modified_time),
+            // 経路上のフォルダと親フォルダのいずれかが、IDまたは名前の一部で一致すればよい
+            TermKind::Folder(ref folder) => file.folder_path.iter()
+                .chain(file.parents.iter())
+                .any(|folder_id| {
+                    folder_id == folder
+                        || folder_names.get(folder_id).is_some_and(|name| name.contains(&term.text))
+                }),
+            TermKind::Owner(_) => file.owners.iter().any(|owner| {
+                normalize(&owner.display_name).contains(&term.text)
+                    || normalize(&owner.email_address).contains(&term.text)
+            }),
+            TermKind::Word(_) | TermKind::Phrase(_) => true,
+        }
+    }
+
+    pub fn status(&self) -> Result<ServiceStatus> {
+        let config = self.config_manager.load_config()?;
+        let last_sync = self.index_store.get_sync_info()?.map(|(last_sync, _)| last_sync);
+
+        Ok(ServiceStatus {
+            config_dir: self.config_manager.config_dir.clone(),
+            storage_backend: config.storage_backend,
+            target_folder_count: config.target_folder_ids.len(),
+            file_count: self.index_store.get_file_count()?,
+            last_sync,
+            authenticated: self.config_manager.load_tokens()?.is_some(),
+            history_file_count: self.history.load().map(|history| history.opens.len()).unwrap_or(0),
+        })
+    }
+
+    // ファイルIDに完全一致するファイル、なければ検索結果の先頭を返す
+    pub fn resolve_file(&self, id_or_query: &str) -> Result<Option<DriveFile>> {
+        if let Some(file) = self.index_store.get_files()?.into_iter().find(|file| file.id == id_or_query) {
+            return Ok(Some(file));
+        }
+        Ok(self.search(id_or_query, 1)?.into_iter().next().map(|result| result.file))
+    }
+
+    // 開くときのURL（prefer_app なら設定されたデスクトップアプリ用のURLを優先する）
+    pub fn open_url(&self, file: &DriveFile, prefer_app: bool) -> Result<String> {
+        if prefer_app {
+            let config = self.config_manager.load_config()?;
+            let app_url = Self::app_kind(&file.mime_type)
+                .and_then(|kind| config.app_url(kind, &file.id, &file.web_view_link));
+            if let Some(app_url) = app_url {
+                return Ok(app_url);
+            }
+        }
+
+        if file.web_view_link.is_empty() {
+            return Err(anyhow::anyhow!("ファイルのURLが保存されていません: {}", file.name).into());
+        }
+        Ok(file.web_view_link.clone())
+    }
+
+    fn app_kind(mime_type: &str) -> Option<&'static str> {
+        match mime_type {
+            "application/vnd.google-apps.document" => Some("document"),
+            "application/vnd.google-apps.spreadsheet" => Some("spreadsheet"),
+            "application/vnd.google-apps.presentation" => Some("presentation"),
+            _ => None,
+        }
+    }
+
+    // ファイルを開いたことを記録する（同期済みでないIDも記録はする）
+    pub fn record_open(&self, file_id: &str) -> Result<Option<DriveFile>> {
+        self.history.record_open(file_id, Utc::now())?;
+        Ok(self.index_store.get_files()?.into_iter().find(|file| file.id == file_id))
+    }
+
+    // 指定日数より古い履歴と、missing_files が真なら同期済みでないファイルの履歴を削除する
+    pub fn prune_history(&self, older_than_days: Option<i64>, missing_files: bool) -> Result<usize> {
+        let known_file_ids: Option<HashSet<String>> = if missing_files {
+            Some(self.index_store.get_files()?.into_iter().map(|file| file.id).collect())
+        } else {
+            None
+        };
+        Ok(self.history.prune(older_than_days.map(Duration::days), known_file_ids.as_ref())?)
+    }
+
+    pub fn export_history(&self) -> Result<Vec<OpenEvent>> {
+        Ok(self.history.export()?)
+    }
+
+    pub fn get_files(&self) -> Result<Vec<DriveFile>> {
+        Ok(self.index_store.get_files()?)
+    }
+
+    pub fn get_folder_names(&self) -> Result<HashMap<String, String>> {
+        Ok(self.index_store.get_folder_names()?)
+    }
+
+    // ファイルのフォルダ経路を "Team / 2025 / Q3" の形式で返す
+    pub fn folder_breadcrumb(file: &DriveFile, folder_names: &HashMap<String, String>) -> String {
+        if file.folder_path.is_empty() {
+            return file.parents.first()
+                .and_then(|parent_id| folder_names.get(parent_id))
+                .cloned()
+                .unwrap_or_default();
+        }
+        build_breadcrumb(&file.folder_path, folder_names)
+    }
+
+    async fn fetch_folder_names_for_sync(
+        drive_client: &GoogleDriveClient,
+        folder_ids: &[String],
+        requests: &Semaphore,
+        progress: &ProgressSender,
+    ) -> HashMap<String, String> {
+        let mut folder_names = HashMap::new();
+        for (folder_id, result) in drive_client.get_folder_infos(folder_ids, requests).await {
+            match result {
+                Ok(folder_info) => {
+                    folder_names.insert(folder_id, folder_info.name);
+                }
+                // 名前が取れなくてもファイルの検索はできるので、同期は続ける
+                Err(e) => {
+                    warn!("フォルダ {} の情報を取得できません: {:#}", folder_id, e);
+                    progress.send(progress_error(&e.context(format!("フォルダ {} の情報を取得できません", folder_id)).into()));
+                }
+            }
+        }
+        folder_names
     }
 
     pub async fn check_and_sync(&mut self) -> Result<()> {
         // 最後の同期から1時間以上経過している場合のみ同期
-        if let Some((last_sync, _)) = self.json_storage.get_sync_info()? {
+        if let Some((last_sync, _)) = self.index_store.get_sync_info()? {
             let now = Utc::now();
             let sync_interval = Duration::hours(1);
             
@@ -169,7 +638,302 @@ impl SearchService {
             }
         }
 
-        println!("定期同期を実行します...");
-        self.sync_files().await
+        // 手動同期などが実行中であれば今回は見送る
+        let Some(_lock) = FileLock::try_acquire(&self.sync_lock_path())? else {
+            info!("別の同期処理が実行中のため、定期同期をスキップします");
+            return Ok(());
+        };
+
+        info!("定期同期を実行します...");
+        self.sync_files_locked(&ProgressSender::disabled()).await
+    }
+}
+
+// 差分同期を諦めて全件同期に切り替える理由
+#[derive(Debug, PartialEq, thiserror::Error)]
+enum FullSyncReason {
+    #[error("保存済みのページトークンが使えなくなりました")]
+    PageTokenExpired,
+    #[error("保存済みのデータにフォルダ経路がありません")]
+    MissingFolderPaths,
+    #[error("取得対象のフォルダが削除されました")]
+    FolderRemoved,
+    #[error("フォルダ構成が変更されました: {0}")]
+    FolderChanged(String),
+}
+
+// 差分同期の失敗が全件同期に切り替える理由であればそれを返し、それ以外は分類したエラーを返す
+fn full_sync_reason(error: anyhow::Error) -> Result<FullSyncReason> {
+    error.downcast::<FullSyncReason>().map_err(Error::from)
+}
+
+impl FullSyncReason {
+    // 変更一覧の取得の失敗のうち、トークンの期限切れ・無効によるもの
+    fn from_changes_error(error: &anyhow::Error) -> Option<Self> {
+        let api_error = error.downcast_ref::<DriveApiError>()?;
+        matches!(api_error.status.as_u16(), 404 | 410).then_some(FullSyncReason::PageTokenExpired)
+    }
+}
+
+// 差分を反映した後の、保存するデータ
+struct AppliedChanges {
+    // 名前順
+    files: Vec<DriveFile>,
+    folder_names: HashMap<String, String>,
+    folder_paths: HashMap<String, Vec<String>>,
+}
+
+// 保存済みのファイル一覧に変更を反映する
+// 差分では反映しきれない変更があれば FullSyncReason のエラーを返す
+fn apply_changes(config: &AppConfig, data: JsonStorageData, changes: Vec<DriveChange>) -> anyhow::Result<AppliedChanges> {
+    if data.folder_paths.is_empty() {
+        return Err(FullSyncReason::MissingFolderPaths.into());
+    }
+
+    let mut files: HashMap<String, DriveFile> = data.files.into_iter()
+        .map(|file| (file.id.clone(), file.into_drive_file()))
+        .collect();
+    let mut folder_names = data.folders;
+    let folder_paths = data.folder_paths;
+
+    for change in changes {
+        let api_file = match change.file {
+            Some(api_file) if !change.removed && !api_file.trashed => api_file,
+            // 完全削除・ゴミ箱への移動
+            _ => {
+                if folder_paths.contains_key(&change.file_id) {
+                    return Err(FullSyncReason::FolderRemoved.into());
+                }
+                files.remove(&change.file_id);
+                continue;
+            }
+        };
+
+        if api_file.folder_target_id().is_some() {
+            if folder_change_requires_full_sync(config, &folder_paths, &api_file) {
+                return Err(FullSyncReason::FolderChanged(api_file.name).into());
+            }
+            // 取得済みフォルダの名前変更を反映する
+            if folder_paths.contains_key(&api_file.id) {
+                folder_names.insert(api_file.id.clone(), api_file.name.clone());
+            }
+            continue;
+        }
+
+        let folder_path = api_file.parents.as_ref()
+            .and_then(|parents| parents.iter().find_map(|parent| folder_paths.get(parent)))
+            .cloned();
+
+        match folder_path {
+            // 追加・名前変更・取得対象フォルダ間の移動
+            Some(folder_path) => {
+                let file = api_file.into_drive_file()?.with_folder_path(folder_path);
+                files.insert(file.id.clone(), file);
+            }
+            // 取得対象フォルダ外への移動
+            None => {
+                files.remove(&api_file.id);
+            }
+        }
+    }
+
+    let mut all_files: Vec<DriveFile> = files.into_values().collect();
+    all_files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(AppliedChanges {
+        files: all_files,
+        folder_names,
+        folder_paths,
+    })
+}
+
+// 差分では反映しきれないフォルダの追加・移動・削除かどうか
+fn folder_change_requires_full_sync(
+    config: &AppConfig,
+    folder_paths: &HashMap<String, Vec<String>>,
+    api_file: &DriveApiFile,
+) -> bool {
+    let parents = api_file.parents.clone().unwrap_or_default();
+
+    if let Some(known_path) = folder_paths.get(&api_file.id) {
+        // 対象フォルダ自体は名前変更のみ反映できる
+        if known_path.len() < 2 {
+            return false;
+        }
+        // 取得済みのサブフォルダが別の場所へ移動した
+        let known_parent = &known_path[known_path.len() - 2];
+        return !parents.contains(known_parent);
+    }
+
+    // 辿る対象のフォルダ配下に、未取得のフォルダ（またはショートカット）が現れた
+    parents.iter()
+        .filter_map(|parent| folder_paths.get(parent))
+        .any(|parent_path| config.can_descend(parent_path))
+}
+
+fn progress_error(error: &Error) -> SyncProgress {
+    SyncProgress::Error {
+        code: error.code().to_string(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::build_storage_files;
+    use crate::models::DriveFile;
+    use crate::test_util::{drive_files, temp_dir, SAMPLE_FILE_NAMES};
+    use reqwest::StatusCode;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    // ディレクトリは返した TempDir を破棄するまで残る
+    fn temp_service() -> (TempDir, SearchService) {
+        let dir = temp_dir();
+        let config_manager = ConfigManager::with_config_dir(dir.path().to_path_buf()).unwrap();
+        config_manager.save_config(&AppConfig::default()).unwrap();
+        (dir, SearchService::with_config_manager(config_manager).unwrap())
+    }
+
+    #[test]
+    fn index_pruning_keeps_the_same_hits_as_a_full_scan() {
+        let (_dir, mut service) = temp_service();
+        let files = drive_files(&[&SAMPLE_FILE_NAMES[..], &["prj notes", "Weekly report 2024"]].concat());
+        service.index_store
+            .save_data(&files, &HashMap::new(), &HashMap::new(), &HashMap::new(), None)
+            .unwrap();
+        let stored = service.index_store.load_data().unwrap().unwrap().files;
+
+        for query in ["prjct", "proposal", "prposal", "議録", "siryou", "bdgt", "wkly rpt", "pro -budget", "pp"] {
+            let parsed = parse_query(query).unwrap();
+            let terms = SearchService::search_terms(&parsed.terms);
+            let mut full_scan: Vec<String> = stored.iter()
+                .cloned()
+                .filter_map(|file| SearchService::score_file(file, &terms, &HashMap::new()))
+                .map(|result| result.file.id)
+                .collect();
+            let mut indexed: Vec<String> = service.search(query, usize::MAX).unwrap()
+                .into_iter()
+                .map(|result| result.file.id)
+                .collect();
+            full_scan.sort();
+            indexed.sort();
+            assert_eq!(indexed, full_scan, "{}", query);
+        }
+
+        let hits: Vec<String> = service.search("prjct", 10).unwrap().into_iter().map(|r| r.file.name).collect();
+        assert!(hits.contains(&"Project Proposal v2".to_string()));
+    }
+
+    const FOLDER: &str = "application/vnd.google-apps.folder";
+
+    // 対象フォルダ root（サブフォルダも辿る）と、その下の sub にファイルが1件ずつある状態
+    fn stored_data() -> JsonStorageData {
+        let files = vec![
+            DriveFile::new("a".to_string(), "Alpha".to_string(), String::new(), Utc::now(), "application/pdf".to_string(), vec!["root".to_string()])
+                .with_folder_path(vec!["root".to_string()]),
+            DriveFile::new("b".to_string(), "Beta".to_string(), String::new(), Utc::now(), "application/pdf".to_string(), vec!["sub".to_string()])
+                .with_folder_path(vec!["root".to_string(), "sub".to_string()]),
+        ];
+        let folders = HashMap::from([
+            ("root".to_string(), "ルート".to_string()),
+            ("sub".to_string(), "サブ".to_string()),
+        ]);
+        JsonStorageData {
+            files: build_storage_files(&files, &folders),
+            folders,
+            folder_paths: HashMap::from([
+                ("root".to_string(), vec!["root".to_string()]),
+                ("sub".to_string(), vec!["root".to_string(), "sub".to_string()]),
+            ]),
+            folder_settings: HashMap::new(),
+            last_sync: Utc::now(),
+            sync_token: Some("token".to_string()),
+        }
+    }
+
+    fn change(file_id: &str, file: Option<serde_json::Value>) -> DriveChange {
+        serde_json::from_value(json!({ "fileId": file_id, "removed": file.is_none(), "file": file })).unwrap()
+    }
+
+    fn api_file(id: &str, name: &str, mime_type: &str, parent: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "name": name,
+            "modifiedTime": "2025-01-01T00:00:00Z",
+            "mimeType": mime_type,
+            "parents": [parent],
+        })
+    }
+
+    #[test]
+    fn applies_changes_or_requires_full_sync() {
+        let mut config = AppConfig {
+            target_folder_ids: vec!["root".to_string()],
+            ..AppConfig::default()
+        };
+        config.folder_settings.insert("root".to_string(), FolderSettings { recursive: true, max_depth: None });
+
+        let mut trashed = api_file("a", "Alpha", "application/pdf", "root");
+        trashed["trashed"] = json!(true);
+
+        let cases = vec![
+            ("追加", vec![change("c", Some(api_file("c", "Gamma", "application/pdf", "sub")))],
+                Ok(vec![("a", "Alpha"), ("b", "Beta"), ("c", "Gamma")])),
+            ("名前変更", vec![change("a", Some(api_file("a", "Alpha 2", "application/pdf", "root")))],
+                Ok(vec![("a", "Alpha 2"), ("b", "Beta")])),
+            ("対象外への移動", vec![change("b", Some(api_file("b", "Beta", "application/pdf", "elsewhere")))],
+                Ok(vec![("a", "Alpha")])),
+            ("ゴミ箱への移動", vec![change("a", Some(trashed))],
+                Ok(vec![("b", "Beta")])),
+            ("完全削除", vec![change("b", None)],
+                Ok(vec![("a", "Alpha")])),
+            ("取得済みフォルダの移動", vec![change("sub", Some(api_file("sub", "サブ", FOLDER, "elsewhere")))],
+                Err(FullSyncReason::FolderChanged("サブ".to_string()))),
+            ("辿る対象のフォルダの追加", vec![change("new", Some(api_file("new", "新規", FOLDER, "sub")))],
+                Err(FullSyncReason::FolderChanged("新規".to_string()))),
+            ("取得済みフォルダの削除", vec![change("sub", None)],
+                Err(FullSyncReason::FolderRemoved)),
+        ];
+
+        for (name, changes, expected) in cases {
+            let result = apply_changes(&config, stored_data(), changes)
+                .map(|applied| applied.files)
+                .map_err(|e| e.downcast::<FullSyncReason>().unwrap());
+            match (result, expected) {
+                (Ok(files), Ok(expected)) => {
+                    let files: Vec<(&str, &str)> = files.iter().map(|file| (file.id.as_str(), file.name.as_str())).collect();
+                    assert_eq!(files, expected, "{}", name);
+                }
+                (Err(reason), Err(expected)) => assert_eq!(reason, expected, "{}", name),
+                (result, expected) => panic!("{}: {:?} / 期待値 {:?}", name, result.map(|files| files.len()), expected),
+            }
+        }
+
+        // 取得済みフォルダの名前変更は差分で反映する
+        let applied = apply_changes(&config, stored_data(), vec![
+            change("sub", Some(api_file("sub", "サブ（改名）", FOLDER, "root"))),
+        ]).unwrap();
+        assert_eq!(applied.folder_names["sub"], "サブ（改名）");
+
+        // ページトークンの期限切れ・無効は全件同期に切り替える理由になる
+        let expired = anyhow::Error::from(DriveApiError::from_response(StatusCode::GONE, "{}"));
+        assert_eq!(FullSyncReason::from_changes_error(&expired), Some(FullSyncReason::PageTokenExpired));
+        let server_error = anyhow::Error::from(DriveApiError::from_response(StatusCode::BAD_GATEWAY, "{}"));
+        assert_eq!(FullSyncReason::from_changes_error(&server_error), None);
+    }
+
+    #[test]
+    fn falls_back_to_full_sync_only_for_full_sync_reasons() {
+        let reason = anyhow::Error::from(FullSyncReason::PageTokenExpired).context("差分同期に失敗しました");
+        assert_eq!(full_sync_reason(reason).unwrap(), FullSyncReason::PageTokenExpired);
+
+        // 一時的な障害・認証・保存データの失敗は全件同期に切り替えず、そのまま返す
+        let server_error = anyhow::Error::from(DriveApiError::from_response(StatusCode::BAD_GATEWAY, "{}"));
+        assert_eq!(full_sync_reason(server_error).unwrap_err().code(), "drive_api");
+        let auth_error = anyhow::Error::from(Error::AuthExpired("失効しました".to_string()));
+        assert_eq!(full_sync_reason(auth_error).unwrap_err().code(), "auth_expired");
+        let io_error = anyhow::Error::from(std::io::Error::other("書き込めません"));
+        assert_eq!(full_sync_reason(io_error).unwrap_err().code(), "io");
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,362 @@
+// ファジーマッチングエンジン
+// 部分文字列一致 > 部分列（飛び飛び）一致・綴り間違いの許容 の順に評価する
+// 範囲はすべて文字単位（char index）で、(開始, 終了) の終了は含まない
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMatch {
+    pub score: f64,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+// 部分文字列一致の基本スコア
+const SUBSTRING_BASE: f64 = 1.0;
+// 先頭一致のボーナス
+const PREFIX_BONUS: f64 = 0.5;
+// 単語の区切りから始まる一致のボーナス
+const BOUNDARY_BONUS: f64 = 0.25;
+// 完全一致のボーナス
+const EXACT_BONUS: f64 = 0.5;
+// 部分列（飛び飛び）一致の最大スコア
+const SUBSEQUENCE_MAX: f64 = 0.8;
+// 綴り間違いを1文字含む一致のスコア（1文字増えるごとに TYPO_STEP ずつ下げる）
+const TYPO_BASE: f64 = 0.7;
+const TYPO_STEP: f64 = 0.2;
+// 綴り間違いの一致が単語の区切りから始まる場合のボーナス
+const TYPO_BOUNDARY_BONUS: f64 = 0.1;
+// 綴り間違いを許容する検索語の長さの範囲（短すぎると何にでも一致し、長いと計算量が増える）
+const TYPO_MIN_CHARS: usize = 4;
+const TYPO_MAX_CHARS: usize = 32;
+
+// 部分列一致の採点（fzf と同様の考え方）
+const SCORE_MATCH: i32 = 16;
+const SCORE_GAP_START: i32 = -3;
+const SCORE_GAP_EXTENSION: i32 = -1;
+// 区切り文字の直後・文字列の先頭
+const BONUS_BOUNDARY: i32 = 8;
+// camelCase の大文字や、英字から数字への切り替わり
+const BONUS_CAMEL: i32 = 7;
+// 直前の文字に続けて一致した場合
+const BONUS_CONSECUTIVE: i32 = 4;
+
+pub fn match_text(query: &str, text: &str) -> Option<TextMatch> {
+    let query: Vec<char> = fold_chars(query);
+    let original: Vec<char> = text.chars().collect();
+    let text: Vec<char> = original.iter().map(|&c| fold_char(c)).collect();
+
+    if query.is_empty() || text.is_empty() {
+        return None;
+    }
+
+    let bonuses = char_bonuses(&original);
+
+    if let Some(substring) = match_substring(&query, &text, &bonuses) {
+        return Some(substring);
+    }
+
+    let subsequence = match_subsequence(&query, &text, &bonuses);
+    let typo = match_typo(&query, &text, &bonuses);
+
+    match (subsequence, typo) {
+        (Some(a), Some(b)) => Some(if a.score >= b.score { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn fold_chars(text: &str) -> Vec<char> {
+    text.chars().map(fold_char).collect()
+}
+
+// 大文字小文字を無視しつつ、文字数（位置）は元の文字列と揃える
+fn fold_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+// 各位置から単語が始まる場合のボーナス
+fn char_bonuses(text: &[char]) -> Vec<i32> {
+    (0..text.len())
+        .map(|i| {
+            if i == 0 || is_boundary(text[i - 1]) {
+                return BONUS_BOUNDARY;
+            }
+            let (prev, current) = (text[i - 1], text[i]);
+            if (prev.is_lowercase() && current.is_uppercase())
+                || (prev.is_alphabetic() && current.is_ascii_digit())
+            {
+                return BONUS_CAMEL;
+            }
+            0
+        })
+        .collect()
+}
+
+fn match_substring(query: &[char], text: &[char], bonuses: &[i32]) -> Option<TextMatch> {
+    if query.len() > text.len() {
+        return None;
+    }
+
+    // 複数箇所に現れる場合は、単語の区切りから始まる箇所を優先する
+    let start = text.windows(query.len())
+        .enumerate()
+        .filter(|(_, window)| *window == query)
+        .map(|(start, _)| start)
+        .max_by_key(|&start| (bonuses[start] > 0, std::cmp::Reverse(start)))?;
+    let end = start + query.len();
+
+    let mut score = SUBSTRING_BASE;
+    if start == 0 {
+        score += PREFIX_BONUS;
+    } else if bonuses[start] > 0 {
+        score += BOUNDARY_BONUS;
+    }
+    if query.len() == text.len() {
+        score += EXACT_BONUS;
+    }
+    // 後方での一致ほど僅かに減点
+    score -= (start as f64 / text.len() as f64) * 0.1;
+
+    Some(TextMatch {
+        score,
+        ranges: vec![(start, end)],
+    })
+}
+
+// 区切りや camelCase の先頭に合う位置を優先して、最もスコアの高い部分列の対応を探す
+fn match_subsequence(query: &[char], text: &[char], bonuses: &[i32]) -> Option<TextMatch> {
+    let (m, n) = (query.len(), text.len());
+    if m > n || !is_subsequence(query, text) {
+        return None;
+    }
+
+    // score[i][j]: query[..=i] を一致させ、query[i] を text[j] に対応させたときの最高点
+    // from[i][j]: そのときの query[i - 1] の位置
+    let mut score = vec![vec![None::<i32>; n]; m];
+    let mut from = vec![vec![0usize; n]; m];
+
+    for j in 0..n {
+        if text[j] == query[0] {
+            score[0][j] = Some(SCORE_MATCH + bonuses[j]);
+        }
+    }
+
+    for i in 1..m {
+        // 直前の query[i - 1] を j - 2 以前で一致させた場合の、間隔の減点込みの最高点
+        let mut best_gap: Option<(i32, usize)> = None;
+        for j in i..n {
+            if j >= 2 {
+                if let Some(prev) = score[i - 1][j - 2] {
+                    let opened = prev + SCORE_GAP_START;
+                    best_gap = match best_gap {
+                        Some((extended, k)) if extended + SCORE_GAP_EXTENSION >= opened => {
+                            Some((extended + SCORE_GAP_EXTENSION, k))
+                        }
+                        _ => Some((opened, j - 2)),
+                    };
+                } else if let Some((extended, k)) = best_gap {
+                    best_gap = Some((extended + SCORE_GAP_EXTENSION, k));
+                }
+            }
+
+            if text[j] != query[i] {
+                continue;
+            }
+
+            let consecutive = score[i - 1][j - 1].map(|prev| prev + BONUS_CONSECUTIVE.max(bonuses[j]));
+            let gapped = best_gap.map(|(gap_score, _)| gap_score + bonuses[j]);
+            score[i][j] = match (consecutive, gapped) {
+                (Some(c), Some(g)) if g > c => {
+                    from[i][j] = best_gap.map(|(_, k)| k).unwrap_or(0);
+                    Some(g + SCORE_MATCH)
+                }
+                (Some(c), _) => {
+                    from[i][j] = j - 1;
+                    Some(c + SCORE_MATCH)
+                }
+                (None, Some(g)) => {
+                    from[i][j] = best_gap.map(|(_, k)| k).unwrap_or(0);
+                    Some(g + SCORE_MATCH)
+                }
+                (None, None) => None,
+            };
+        }
+    }
+
+    let (mut j, best) = (0..n)
+        .filter_map(|j| score[m - 1][j].map(|s| (j, s)))
+        .max_by_key(|&(j, s)| (s, std::cmp::Reverse(j)))?;
+
+    let mut positions = vec![0; m];
+    for i in (0..m).rev() {
+        positions[i] = j;
+        if i > 0 {
+            j = from[i][j];
+        }
+    }
+
+    let ideal = m as i32 * (SCORE_MATCH + BONUS_BOUNDARY);
+    let ratio = (best as f64 / ideal as f64).clamp(0.0, 1.0);
+
+    Some(TextMatch {
+        score: SUBSEQUENCE_MAX * ratio,
+        ranges: merge_positions(&positions),
+    })
+}
+
+fn is_subsequence(query: &[char], text: &[char]) -> bool {
+    let mut text_iter = text.iter();
+    query.iter().all(|qc| text_iter.any(|tc| tc == qc))
+}
+
+fn max_typos(query_len: usize) -> usize {
+    if query_len >= 8 { 2 } else { 1 }
+}
+
+// 綴り間違い（挿入・削除・置換・隣接文字の入れ替え）を許容して、テキスト中の一部と照合する
+// 編集距離は Damerau–Levenshtein（制限版）で、距離の上限は検索語の長さで決まる
+fn match_typo(query: &[char], text: &[char], bonuses: &[i32]) -> Option<TextMatch> {
+    let m = query.len();
+    if !(TYPO_MIN_CHARS..=TYPO_MAX_CHARS).contains(&m) {
+        return None;
+    }
+    let limit = max_typos(m);
+    let n = text.len();
+
+    // dist[i][j]: query[..i] とテキストの j 文字目で終わる部分との最小距離（開始位置は自由）
+    // start[i][j]: そのときのテキスト上の開始位置
+    let mut dist = vec![vec![0usize; n + 1]; m + 1];
+    let mut start = vec![vec![0usize; n + 1]; m + 1];
+    for (j, s) in start[0].iter_mut().enumerate() {
+        *s = j;
+    }
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    let mut prev_row_min = 0;
+    for i in 1..=m {
+        let mut row_min = usize::MAX;
+        for j in 1..=n {
+            let cost = usize::from(query[i - 1] != text[j - 1]);
+            let mut best = (dist[i - 1][j - 1] + cost, start[i - 1][j - 1]);
+            if dist[i - 1][j] + 1 < best.0 {
+                best = (dist[i - 1][j] + 1, start[i - 1][j]);
+            }
+            if dist[i][j - 1] + 1 < best.0 {
+                best = (dist[i][j - 1] + 1, start[i][j - 1]);
+            }
+            if i > 1 && j > 1 && query[i - 1] == text[j - 2] && query[i - 2] == text[j - 1]
+                && dist[i - 2][j - 2] + 1 < best.0
+            {
+                best = (dist[i - 2][j - 2] + 1, start[i - 2][j - 2]);
+            }
+            (dist[i][j], start[i][j]) = best;
+            row_min = row_min.min(best.0);
+        }
+        // 連続する2行で上限に達したら、以降の行（入れ替えを含む）で上限内に戻ることはない
+        if row_min > limit && prev_row_min >= limit {
+            return None;
+        }
+        prev_row_min = row_min;
+    }
+
+    let (distance, match_start, match_end) = (1..=n)
+        .map(|j| (dist[m][j], start[m][j], j))
+        .filter(|&(d, s, e)| d <= limit && e > s)
+        // 距離が同じなら、単語の先頭から始まり、単語の終わりで終わる一致を選ぶ
+        .min_by_key(|&(d, s, e)| (d, bonuses[s] == 0, s, !(e == n || is_boundary(text[e]) || bonuses[e] > 0)))?;
+
+    let mut score = TYPO_BASE - TYPO_STEP * (distance.saturating_sub(1)) as f64;
+    if bonuses[match_start] > 0 {
+        score += TYPO_BOUNDARY_BONUS;
+    }
+
+    Some(TextMatch {
+        score,
+        ranges: vec![(match_start, match_end)],
+    })
+}
+
+fn merge_positions(positions: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+
+    for &pos in positions {
+        match ranges.last_mut() {
+            Some((_, end)) if *end == pos => *end = pos + 1,
+            _ => ranges.push((pos, pos + 1)),
+        }
+    }
+
+    ranges
+}
+
+pub fn merge_ranges(mut ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    ranges.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+fn is_boundary(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '_' | '-' | '.' | '/' | '(' | ')' | '[' | ']' | '【' | '】' | '「' | '」' | '・' | '　')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_prefix_and_boundary_substrings() {
+        let prefix = match_text("pro", "Project Proposal").unwrap();
+        assert_eq!(prefix.ranges, vec![(0, 3)]);
+
+        // 単語の途中より、単語の先頭での一致を選ぶ
+        let boundary = match_text("pos", "compose_position").unwrap();
+        assert_eq!(boundary.ranges, vec![(8, 11)]);
+        assert!(prefix.score > boundary.score);
+
+        let exact = match_text("proposal", "Proposal").unwrap();
+        assert!(exact.score > prefix.score);
+    }
+
+    #[test]
+    fn scores_subsequences_on_word_starts() {
+        let initials = match_text("pp", "Project Proposal").unwrap();
+        assert_eq!(initials.ranges, vec![(0, 1), (8, 9)]);
+
+        let camel = match_text("qr", "quarterlyReport").unwrap();
+        assert_eq!(camel.ranges, vec![(0, 1), (9, 10)]);
+
+        let scattered = match_text("qr", "aquariumr").unwrap();
+        assert!(camel.score > scattered.score);
+    }
+
+    #[test]
+    fn tolerates_typos() {
+        let missing = match_text("prposal", "Project Proposal v2").unwrap();
+        assert_eq!(missing.ranges, vec![(8, 16)]);
+
+        let swapped = match_text("porposal", "proposal").unwrap();
+        assert_eq!(swapped.ranges, vec![(0, 8)]);
+
+        let substituted = match_text("meetimg", "weekly meeting notes").unwrap();
+        assert_eq!(substituted.ranges, vec![(7, 14)]);
+
+        // 短い語や、上限を超える間違いは一致させない
+        assert!(match_text("xyz", "abc").is_none());
+        assert!(match_text("budget", "project proposal").is_none());
+    }
+
+    #[test]
+    fn ranks_match_kinds() {
+        let substring = match_text("proposal", "project proposal").unwrap();
+        let typo = match_text("prposal", "project proposal").unwrap();
+        assert!(substring.score > typo.score);
+        assert!(typo.score > 0.0);
+    }
+}
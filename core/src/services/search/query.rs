@@ -0,0 +1,419 @@
+// 検索クエリの構文解析
+// 例: type:sheet folder:営業 modified:>2025-01-01 "完全一致の語句" -draft
+// 位置はすべて元のクエリでの文字単位（char index）で、(開始, 終了) の終了は含まない
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub terms: Vec<QueryTerm>,
+    // 検索は続けられるが、利用者に知らせたい箇所
+    pub diagnostics: Vec<QueryDiagnostic>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTerm {
+    // 先頭に - が付いた除外条件
+    pub negated: bool,
+    pub kind: TermKind,
+    pub span: (usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TermKind {
+    // ファジー検索する単語
+    Word(String),
+    // "..." で囲んだ語句（部分文字列として一致させる）
+    Phrase(String),
+    Type(FileType),
+    Folder(String),
+    Modified(DateFilter),
+    Owner(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileType {
+    Document,
+    Spreadsheet,
+    Presentation,
+    Form,
+    Drawing,
+    Folder,
+    Pdf,
+    Image,
+    Video,
+    Audio,
+    // type:application/zip のようにMIMEタイプを直接指定したもの
+    MimeType(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Before,
+    OnOrBefore,
+    On,
+    OnOrAfter,
+    After,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateFilter {
+    pub comparison: Comparison,
+    pub date: NaiveDate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryParseError {
+    pub message: String,
+    pub span: (usize, usize),
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}（{}文字目）", self.message, self.span.0 + 1)
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+// 既知のフィルタのキーの打ち間違いらしいもの（「tpye:pdf」など）。検索語として扱った上で知らせる
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryDiagnostic {
+    pub key: String,
+    pub suggestion: String,
+    pub span: (usize, usize),
+}
+
+impl fmt::Display for QueryDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "「{}:」はフィルタではなく検索語として扱います（「{}:」の誤りではありませんか）", self.key, self.suggestion)
+    }
+}
+
+const FILTER_KEYS: [&str; 4] = ["type", "folder", "modified", "owner"];
+const TYPE_NAMES: &str = "doc, sheet, slide, form, drawing, folder, pdf, image, video, audio";
+
+impl FileType {
+    fn parse(value: &str) -> Option<Self> {
+        let file_type = match value.to_lowercase().as_str() {
+            "doc" | "docs" | "document" => FileType::Document,
+            "sheet" | "sheets" | "spreadsheet" => FileType::Spreadsheet,
+            "slide" | "slides" | "presentation" => FileType::Presentation,
+            "form" | "forms" => FileType::Form,
+            "drawing" => FileType::Drawing,
+            "folder" => FileType::Folder,
+            "pdf" => FileType::Pdf,
+            "image" => FileType::Image,
+            "video" => FileType::Video,
+            "audio" => FileType::Audio,
+            mime if mime.contains('/') => FileType::MimeType(mime.to_string()),
+            _ => return None,
+        };
+        Some(file_type)
+    }
+
+    pub fn matches(&self, mime_type: &str) -> bool {
+        match self {
+            FileType::Document => matches!(
+                mime_type,
+                "application/vnd.google-apps.document"
+                    | "application/msword"
+                    | "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            ),
+            FileType::Spreadsheet => matches!(
+                mime_type,
+                "application/vnd.google-apps.spreadsheet"
+                    | "application/vnd.ms-excel"
+                    | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+                    | "text/csv"
+            ),
+            FileType::Presentation => matches!(
+                mime_type,
+                "application/vnd.google-apps.presentation"
+                    | "application/vnd.ms-powerpoint"
+                    | "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+            ),
+            FileType::Form => mime_type == "application/vnd.google-apps.form",
+            FileType::Drawing => mime_type == "application/vnd.google-apps.drawing",
+            FileType::Folder => mime_type == "application/vnd.google-apps.folder",
+            FileType::Pdf => mime_type == "application/pdf",
+            FileType::Image => mime_type.starts_with("image/"),
+            FileType::Video => mime_type.starts_with("video/"),
+            FileType::Audio => mime_type.starts_with("audio/"),
+            FileType::MimeType(mime) => mime_type.eq_ignore_ascii_case(mime),
+        }
+    }
+}
+
+impl DateFilter {
+    // 日付はローカルタイムゾーンの日付で比較する
+    pub fn matches(&self, modified_time: &DateTime<Utc>) -> bool {
+        let date = modified_time.with_timezone(&Local).date_naive();
+        match self.comparison {
+            Comparison::Before => date < self.date,
+            Comparison::OnOrBefore => date <= self.date,
+            Comparison::On => date == self.date,
+            Comparison::OnOrAfter => date >= self.date,
+            Comparison::After => date > self.date,
+        }
+    }
+}
+
+pub fn parse_query(input: &str) -> Result<Query, QueryParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut terms = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        if chars[pos].is_whitespace() {
+            pos += 1;
+            continue;
+        }
+
+        let start = pos;
+        // 単独の - は除外ではなく普通の単語として扱う
+        let negated = chars[pos] == '-' && chars.get(pos + 1).is_some_and(|c| !c.is_whitespace());
+        if negated {
+            pos += 1;
+        }
+
+        let kind = if chars[pos] == '"' {
+            let (phrase, end) = read_quoted(&chars, pos)?;
+            pos = end;
+            if phrase.trim().is_empty() {
+                return Err(QueryParseError {
+                    message: "空の語句は検索できません".to_string(),
+                    span: (start, pos),
+                });
+            }
+            TermKind::Phrase(phrase)
+        } else if let Some((key, key_end)) = filter_key(&chars, pos) {
+            let value_start = key_end + 1;
+            let (value, end) = if chars.get(value_start) == Some(&'"') {
+                read_quoted(&chars, value_start)?
+            } else {
+                read_word(&chars, value_start)
+            };
+            pos = end;
+            if value.trim().is_empty() {
+                return Err(QueryParseError {
+                    message: format!("「{}:」の後に値を指定してください", key),
+                    span: (start, pos),
+                });
+            }
+
+            parse_filter(&key, &value, (value_start, end))?
+        } else {
+            diagnostics.extend(misspelled_filter_key(&chars, pos));
+            let (word, end) = read_word(&chars, pos);
+            pos = end;
+            TermKind::Word(word)
+        };
+
+        terms.push(QueryTerm {
+            negated,
+            kind,
+            span: (start, pos),
+        });
+    }
+
+    Ok(Query { terms, diagnostics })
+}
+
+fn parse_filter(key: &str, value: &str, span: (usize, usize)) -> Result<TermKind, QueryParseError> {
+    match key {
+        "type" => FileType::parse(value)
+            .map(TermKind::Type)
+            .ok_or_else(|| QueryParseError {
+                message: format!("不明な種類「{}」です（使えるもの: {}、またはMIMEタイプ）", value, TYPE_NAMES),
+                span,
+            }),
+        "folder" => Ok(TermKind::Folder(value.to_string())),
+        "owner" => Ok(TermKind::Owner(value.to_string())),
+        "modified" => parse_date_filter(value, span).map(TermKind::Modified),
+        _ => unreachable!("FILTER_KEYS にないキー: {}", key),
+    }
+}
+
+fn parse_date_filter(value: &str, span: (usize, usize)) -> Result<DateFilter, QueryParseError> {
+    let (comparison, date_text) = [
+        (">=", Comparison::OnOrAfter),
+        ("<=", Comparison::OnOrBefore),
+        (">", Comparison::After),
+        ("<", Comparison::Before),
+        ("=", Comparison::On),
+    ]
+    .iter()
+    .find_map(|(op, comparison)| value.strip_prefix(op).map(|rest| (*comparison, rest)))
+    .unwrap_or((Comparison::On, value));
+
+    let op_len = value.chars().count() - date_text.chars().count();
+    let date = NaiveDate::parse_from_str(date_text, "%Y-%m-%d").map_err(|_| QueryParseError {
+        message: format!("日付「{}」を解釈できません（例: modified:>2025-01-01）", date_text),
+        span: (span.0 + op_len, span.1),
+    })?;
+
+    Ok(DateFilter { comparison, date })
+}
+
+// フィルタ（key: の形で key が既知のもの）なら、小文字にした key と終了位置（: の位置）を返す
+// 「Re:」「TODO:fix」や URL などは普通の単語として扱う
+fn filter_key(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let (key, end) = key_candidate(chars, start)?;
+    FILTER_KEYS.contains(&key.as_str()).then_some((key, end))
+}
+
+// 既知の key と1文字だけ違う key: は打ち間違いとみなす
+fn misspelled_filter_key(chars: &[char], start: usize) -> Option<QueryDiagnostic> {
+    let (key, end) = key_candidate(chars, start)?;
+    let suggestion = FILTER_KEYS.iter().find(|known| edit_distance(&key, known) == 1)?;
+    Some(QueryDiagnostic {
+        key,
+        suggestion: suggestion.to_string(),
+        span: (start, end),
+    })
+}
+
+// 英字の並びの直後に : があれば、小文字にしたその並びと : の位置
+fn key_candidate(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut pos = start;
+    while pos < chars.len() && chars[pos].is_ascii_alphabetic() {
+        pos += 1;
+    }
+    if pos == start || chars.get(pos) != Some(&':') {
+        return None;
+    }
+    Some((chars[start..pos].iter().collect::<String>().to_lowercase(), pos))
+}
+
+// 編集距離（挿入・削除・置換・隣接文字の入れ替えを1とする）
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut dist = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, d) in dist[0].iter_mut().enumerate() {
+        *d = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (dist[i - 1][j] + 1).min(dist[i][j - 1] + 1).min(dist[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(dist[i - 2][j - 2] + 1);
+            }
+            dist[i][j] = best;
+        }
+    }
+    dist[a.len()][b.len()]
+}
+
+fn read_word(chars: &[char], start: usize) -> (String, usize) {
+    let mut pos = start;
+    while pos < chars.len() && !chars[pos].is_whitespace() {
+        pos += 1;
+    }
+    (chars[start..pos].iter().collect(), pos)
+}
+
+// start の位置にある " から対応する " までを読み、閉じ引用符の次の位置を返す
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryParseError> {
+    let content_start = start + 1;
+    match chars[content_start..].iter().position(|&c| c == '"') {
+        Some(offset) => {
+            let end = content_start + offset;
+            Ok((chars[content_start..end].iter().collect(), end + 1))
+        }
+        None => Err(QueryParseError {
+            message: "引用符が閉じられていません".to_string(),
+            span: (start, chars.len()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<(bool, TermKind)> {
+        parse_query(input).unwrap().terms.into_iter().map(|term| (term.negated, term.kind)).collect()
+    }
+
+    #[test]
+    fn parses_words_phrases_and_filters() {
+        assert_eq!(
+            kinds(r#"type:sheet folder:営業 modified:>2025-01-01 "exact phrase" -draft"#),
+            vec![
+                (false, TermKind::Type(FileType::Spreadsheet)),
+                (false, TermKind::Folder("営業".to_string())),
+                (false, TermKind::Modified(DateFilter {
+                    comparison: Comparison::After,
+                    date: NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(),
+                })),
+                (false, TermKind::Phrase("exact phrase".to_string())),
+                (true, TermKind::Word("draft".to_string())),
+            ]
+        );
+        assert_eq!(
+            kinds(r#"owner:tanaka -folder:"アーカイブ 2024" - 資料"#),
+            vec![
+                (false, TermKind::Owner("tanaka".to_string())),
+                (true, TermKind::Folder("アーカイブ 2024".to_string())),
+                (false, TermKind::Word("-".to_string())),
+                (false, TermKind::Word("資料".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn treats_unknown_keys_as_words() {
+        assert_eq!(
+            kinds("Re: 議事録 TODO:fix https://example.com/a tpye:pdf"),
+            vec![
+                (false, TermKind::Word("Re:".to_string())),
+                (false, TermKind::Word("議事録".to_string())),
+                (false, TermKind::Word("TODO:fix".to_string())),
+                (false, TermKind::Word("https://example.com/a".to_string())),
+                (false, TermKind::Word("tpye:pdf".to_string())),
+            ]
+        );
+        // 既知のキーの打ち間違いらしいものは、検索語として扱った上で知らせる
+        assert_eq!(
+            parse_query("Re: 議事録 TODO:fix https://example.com/a -tpye:pdf").unwrap().diagnostics,
+            vec![QueryDiagnostic {
+                key: "tpye".to_string(),
+                suggestion: "type".to_string(),
+                span: (40, 44),
+            }]
+        );
+        assert_eq!(edit_distance("tpye", "type"), 1);
+        assert_eq!(edit_distance("folde", "folder"), 1);
+        assert_eq!(edit_distance("re", "type"), 3);
+    }
+
+    #[test]
+    fn records_term_positions() {
+        let query = parse_query("見積 -type:pdf").unwrap();
+        assert_eq!(query.terms[0].span, (0, 2));
+        assert_eq!(query.terms[1].span, (3, 12));
+    }
+
+    #[test]
+    fn reports_errors_with_positions() {
+        let error = parse_query("type:spreadsheeet").unwrap_err();
+        assert_eq!(error.span, (5, 17));
+
+        let error = parse_query("modified:>2025-13-01").unwrap_err();
+        assert_eq!(error.span, (10, 20));
+
+        let error = parse_query(r#"a "unterminated"#).unwrap_err();
+        assert_eq!(error.span, (2, 15));
+
+        let error = parse_query("folder: x").unwrap_err();
+        assert_eq!(error.span, (0, 7));
+    }
+}
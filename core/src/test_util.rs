@@ -0,0 +1,33 @@
+// テストで共通に使うデータと一時ディレクトリ
+
+use chrono::Utc;
+use tempfile::TempDir;
+
+use crate::models::DriveFile;
+
+// 英語・漢字・かな・拡張子つきの名前を1つずつ
+pub const SAMPLE_FILE_NAMES: [&str; 4] = ["Project Proposal v2", "営業会議_議事録", "キャンプ ガイド しりょう", "budget.xlsx"];
+
+// 名前の順に "0", "1", … の ID を付けたファイル
+pub fn drive_files(names: &[&str]) -> Vec<DriveFile> {
+    names.iter()
+        .enumerate()
+        .map(|(i, name)| DriveFile::new(
+            i.to_string(),
+            name.to_string(),
+            String::new(),
+            Utc::now(),
+            "application/pdf".to_string(),
+            Vec::new(),
+        ))
+        .collect()
+}
+
+pub fn sample_files() -> Vec<DriveFile> {
+    drive_files(&SAMPLE_FILE_NAMES)
+}
+
+// 破棄するときに中身ごと消える一時ディレクトリ
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new().prefix("fds-").tempdir().unwrap()
+}
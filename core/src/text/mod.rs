@@ -0,0 +1,3 @@
+pub mod normalize;
+pub mod reading;
+pub mod romaji;
@@ -0,0 +1,108 @@
+// 検索用の文字列正規化
+// NFKC（全角英数字・半角カナなどの互換文字）、大文字小文字、カタカナ→ひらがな、
+// ダッシュ・空白の表記ゆれをまとめて、見た目が同じ文字列が一致するようにする
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::text::romaji::katakana_to_hiragana;
+
+// 半角カナの濁点・半濁点（直前の文字と合わせて1文字になる）
+const HALFWIDTH_VOICED_MARKS: [char; 2] = ['\u{FF9E}', '\u{FF9F}'];
+
+pub fn normalize(text: &str) -> String {
+    normalize_with_map(text).0
+}
+
+// 正規化後の文字列と、正規化後の各文字が元の文字列の何文字目に由来するかを返す
+// 一致範囲を元のファイル名の位置に戻すのに使う
+pub fn normalize_with_map(text: &str) -> (String, Vec<usize>) {
+    normalize_chars(text, true)
+}
+
+// 大文字小文字を残して正規化する（camelCase の区切りを照合に使う場合）
+pub fn normalize_with_map_keep_case(text: &str) -> (String, Vec<usize>) {
+    normalize_chars(text, false)
+}
+
+fn normalize_chars(text: &str, lowercase: bool) -> (String, Vec<usize>) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut normalized = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(chars.len());
+
+    let mut i = 0;
+    while i < chars.len() {
+        // 基底文字と、それに続く結合文字をまとめて正規化する
+        let start = i;
+        i += 1;
+        while i < chars.len() && (is_combining_mark(chars[i]) || HALFWIDTH_VOICED_MARKS.contains(&chars[i])) {
+            i += 1;
+        }
+
+        let cluster: String = chars[start..i].iter().collect();
+        for c in cluster.nfkc() {
+            if lowercase {
+                for lower in c.to_lowercase() {
+                    normalized.push(fold_char(lower));
+                    origins.push(start);
+                }
+            } else {
+                normalized.push(fold_char(c));
+                origins.push(start);
+            }
+        }
+    }
+
+    (normalized, origins)
+}
+
+// 正規化後の文字位置の範囲を、元の文字列での範囲に戻す
+pub fn map_range_to_original(origins: &[usize], original_len: usize, (start, end): (usize, usize)) -> (usize, usize) {
+    let original_start = origins.get(start).copied().unwrap_or(original_len);
+    let original_end = origins.get(end).copied().unwrap_or(original_len);
+    (original_start, original_end.max(original_start + 1).min(original_len))
+}
+
+fn fold_char(c: char) -> char {
+    match c {
+        // ハイフン・ダッシュ・マイナス記号
+        '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{FE58}' | '\u{FE63}' => '-',
+        // 波ダッシュ
+        '\u{301C}' | '\u{3030}' => '~',
+        c if c.is_whitespace() => ' ',
+        c => katakana_to_hiragana(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_visually_equal_strings() {
+        let cases = [
+            ("ＡＢＣ２０２５", "abc2025"),
+            ("ｶﾞｲﾄﾞ", "がいど"),
+            ("ガイド", "がいど"),
+            ("がいど", "がいど"),
+            ("Project　Proposal", "project proposal"),
+            ("2025‐01–02—03", "2025-01-02-03"),
+            ("ﾐｰﾃｨﾝｸﾞ", "みーてぃんぐ"),
+            ("㈱営業", "(株)営業"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(normalize(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn maps_normalized_positions_to_original() {
+        // ｶﾞ は2文字で1文字（が）に、㈱ は1文字で3文字になる
+        let (normalized, origins) = normalize_with_map("ｶﾞｲﾄﾞ㈱");
+        assert_eq!(normalized, "がいど(株)");
+        assert_eq!(origins, vec![0, 2, 3, 5, 5, 5]);
+        assert_eq!(map_range_to_original(&origins, 6, (0, 3)), (0, 5));
+        assert_eq!(map_range_to_original(&origins, 6, (3, 6)), (5, 6));
+    }
+}
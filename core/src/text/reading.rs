@@ -0,0 +1,92 @@
+// 漢字を含む名前の読み（ひらがな）を形態素解析で取得する
+// japanese-tokenizer 機能が無効な場合は読みを返さない
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenReading {
+    pub surface: String,
+    // ひらがなの読み
+    pub reading: String,
+}
+
+#[cfg(feature = "japanese-tokenizer")]
+mod tokenizer {
+    use std::borrow::Cow;
+
+    use lindera::dictionary::load_dictionary;
+    use lindera::mode::Mode;
+    use lindera::segmenter::Segmenter;
+
+    use super::TokenReading;
+    use crate::text::romaji::katakana_to_hiragana;
+
+    thread_local! {
+        // 辞書の読み込みは重いので、スレッドごとに一度だけ行う
+        static SEGMENTER: Option<Segmenter> = load_dictionary("embedded://ipadic")
+            .map(|dictionary| Segmenter::new(Mode::Normal, dictionary, None))
+            .map_err(|e| tracing::warn!("形態素解析の辞書を読み込めませんでした: {}", e))
+            .ok();
+    }
+
+    pub fn token_readings(text: &str) -> Vec<TokenReading> {
+        SEGMENTER.with(|segmenter| {
+            let Some(segmenter) = segmenter else {
+                return Vec::new();
+            };
+            let Ok(mut tokens) = segmenter.segment(Cow::Borrowed(text)) else {
+                return Vec::new();
+            };
+
+            tokens.iter_mut()
+                .filter_map(|token| {
+                    // 辞書にない語は読みが "*" になる
+                    let reading = token.get("reading")
+                        .filter(|reading| !reading.is_empty() && *reading != "*")
+                        .map(|reading| reading.chars().map(katakana_to_hiragana).collect::<String>())?;
+                    Some(TokenReading {
+                        surface: token.surface.to_string(),
+                        reading,
+                    })
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(feature = "japanese-tokenizer")]
+pub use tokenizer::token_readings;
+
+#[cfg(not(feature = "japanese-tokenizer"))]
+pub fn token_readings(_text: &str) -> Vec<TokenReading> {
+    Vec::new()
+}
+
+// 漢字を含む語の読みと、名前全体をつなげた読みを返す
+pub fn readings_for(text: &str) -> Vec<String> {
+    let tokens = token_readings(text);
+
+    let mut readings: Vec<String> = tokens.iter()
+        .filter(|token| token.surface != token.reading)
+        .map(|token| token.reading.clone())
+        .collect();
+
+    // 複数の語にまたがる入力（eigyoukaigi など）にも一致させる
+    if tokens.len() > 1 {
+        readings.push(tokens.iter().map(|token| token.reading.as_str()).collect());
+    }
+
+    readings.sort();
+    readings.dedup();
+    readings
+}
+
+#[cfg(all(test, feature = "japanese-tokenizer"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_kanji_as_hiragana() {
+        assert!(readings_for("議事録").contains(&"ぎじろく".to_string()));
+        // 複数の語からなる名前は、つなげた読みも返す
+        assert!(readings_for("営業会議").contains(&"えいぎょうかいぎ".to_string()));
+    }
+}
@@ -0,0 +1,481 @@
+// かな→ローマ字変換（ヘボン式・訓令式・日本式）と、入力されたローマ字の正規化
+// カタカナはひらがなに揃えてから変換する
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const LONG_VOWEL_MARK: char = 'ー';
+const SOKUON: char = 'っ';
+
+// 拗音・外来語表記など、2文字で1音になる組み合わせ
+const DIGRAPHS: &[(char, char, &str)] = &[
+    ('き', 'ゃ', "kya"), ('き', 'ゅ', "kyu"), ('き', 'ょ', "kyo"),
+    ('ぎ', 'ゃ', "gya"), ('ぎ', 'ゅ', "gyu"), ('ぎ', 'ょ', "gyo"),
+    ('し', 'ゃ', "sha"), ('し', 'ゅ', "shu"), ('し', 'ょ', "sho"), ('し', 'ぇ', "she"),
+    ('じ', 'ゃ', "ja"), ('じ', 'ゅ', "ju"), ('じ', 'ょ', "jo"), ('じ', 'ぇ', "je"),
+    ('ち', 'ゃ', "cha"), ('ち', 'ゅ', "chu"), ('ち', 'ょ', "cho"), ('ち', 'ぇ', "che"),
+    ('ぢ', 'ゃ', "ja"), ('ぢ', 'ゅ', "ju"), ('ぢ', 'ょ', "jo"),
+    ('に', 'ゃ', "nya"), ('に', 'ゅ', "nyu"), ('に', 'ょ', "nyo"),
+    ('ひ', 'ゃ', "hya"), ('ひ', 'ゅ', "hyu"), ('ひ', 'ょ', "hyo"),
+    ('び', 'ゃ', "bya"), ('び', 'ゅ', "byu"), ('び', 'ょ', "byo"),
+    ('ぴ', 'ゃ', "pya"), ('ぴ', 'ゅ', "pyu"), ('ぴ', 'ょ', "pyo"),
+    ('み', 'ゃ', "mya"), ('み', 'ゅ', "myu"), ('み', 'ょ', "myo"),
+    ('り', 'ゃ', "rya"), ('り', 'ゅ', "ryu"), ('り', 'ょ', "ryo"),
+    ('ふ', 'ぁ', "fa"), ('ふ', 'ぃ', "fi"), ('ふ', 'ぇ', "fe"), ('ふ', 'ぉ', "fo"), ('ふ', 'ゅ', "fyu"),
+    ('て', 'ぃ', "ti"), ('て', 'ゅ', "tyu"), ('で', 'ぃ', "di"), ('で', 'ゅ', "dyu"),
+    ('と', 'ぅ', "tu"), ('ど', 'ぅ', "du"),
+    ('つ', 'ぁ', "tsa"), ('つ', 'ぃ', "tsi"), ('つ', 'ぇ', "tse"), ('つ', 'ぉ', "tso"),
+    ('う', 'ぃ', "wi"), ('う', 'ぇ', "we"), ('う', 'ぉ', "wo"),
+    ('い', 'ぇ', "ye"),
+    ('く', 'ぁ', "kwa"), ('ぐ', 'ぁ', "gwa"),
+    ('ゔ', 'ぁ', "va"), ('ゔ', 'ぃ', "vi"), ('ゔ', 'ぇ', "ve"), ('ゔ', 'ぉ', "vo"), ('ゔ', 'ゅ', "vyu"),
+];
+
+const MONOGRAPHS: &[(char, &str)] = &[
+    ('あ', "a"), ('い', "i"), ('う', "u"), ('え', "e"), ('お', "o"),
+    ('か', "ka"), ('き', "ki"), ('く', "ku"), ('け', "ke"), ('こ', "ko"),
+    ('が', "ga"), ('ぎ', "gi"), ('ぐ', "gu"), ('げ', "ge"), ('ご', "go"),
+    ('さ', "sa"), ('し', "shi"), ('す', "su"), ('せ', "se"), ('そ', "so"),
+    ('ざ', "za"), ('じ', "ji"), ('ず', "zu"), ('ぜ', "ze"), ('ぞ', "zo"),
+    ('た', "ta"), ('ち', "chi"), ('つ', "tsu"), ('て', "te"), ('と', "to"),
+    ('だ', "da"), ('ぢ', "ji"), ('づ', "zu"), ('で', "de"), ('ど', "do"),
+    ('な', "na"), ('に', "ni"), ('ぬ', "nu"), ('ね', "ne"), ('の', "no"),
+    ('は', "ha"), ('ひ', "hi"), ('ふ', "fu"), ('へ', "he"), ('ほ', "ho"),
+    ('ば', "ba"), ('び', "bi"), ('ぶ', "bu"), ('べ', "be"), ('ぼ', "bo"),
+    ('ぱ', "pa"), ('ぴ', "pi"), ('ぷ', "pu"), ('ぺ', "pe"), ('ぽ', "po"),
+    ('ま', "ma"), ('み', "mi"), ('む', "mu"), ('め', "me"), ('も', "mo"),
+    ('や', "ya"), ('ゆ', "yu"), ('よ', "yo"),
+    ('ら', "ra"), ('り', "ri"), ('る', "ru"), ('れ', "re"), ('ろ', "ro"),
+    ('わ', "wa"), ('ゐ', "i"), ('ゑ', "e"), ('を', "o"),
+    // 「ん」は検索時の入力に合わせて、母音の前でも区切り記号を付けない
+    ('ん', "n"),
+    ('ゔ', "vu"),
+    // 単独で現れた小書き文字
+    ('ぁ', "a"), ('ぃ', "i"), ('ぅ', "u"), ('ぇ', "e"), ('ぉ', "o"),
+    ('ゃ', "ya"), ('ゅ', "yu"), ('ょ', "yo"), ('ゎ', "wa"), ('ゕ', "ka"), ('ゖ', "ke"),
+];
+
+// カタカナのみに存在する文字（ヷ・ヸ・ヹ・ヺ）
+const KATAKANA_ONLY: &[(char, &str)] = &[
+    ('ヷ', "va"), ('ヸ', "vi"), ('ヹ', "ve"), ('ヺ', "vo"),
+];
+
+// 訓令式でヘボン式と綴りが異なるもの
+const KUNREI_OVERRIDES: &[(&str, &str)] = &[
+    ("し", "si"), ("ち", "ti"), ("つ", "tu"), ("ふ", "hu"), ("じ", "zi"), ("ぢ", "zi"), ("づ", "zu"),
+    ("しゃ", "sya"), ("しゅ", "syu"), ("しょ", "syo"),
+    ("ちゃ", "tya"), ("ちゅ", "tyu"), ("ちょ", "tyo"),
+    ("じゃ", "zya"), ("じゅ", "zyu"), ("じょ", "zyo"),
+    ("ぢゃ", "zya"), ("ぢゅ", "zyu"), ("ぢょ", "zyo"),
+];
+
+// 日本式で訓令式と綴りが異なるもの
+const NIHON_OVERRIDES: &[(&str, &str)] = &[
+    ("ぢ", "di"), ("づ", "du"),
+    ("ぢゃ", "dya"), ("ぢゅ", "dyu"), ("ぢょ", "dyo"),
+    ("ゐ", "wi"), ("ゑ", "we"), ("を", "wo"),
+];
+
+// IMEで使われる入力のうち、各方式の綴りからは導けないもの（こちらを優先する）
+const IME_INPUTS: &[(&str, &str)] = &[
+    ("wi", "うぃ"), ("we", "うぇ"),
+    ("n'", "ん"), ("xn", "ん"), ("-", "ー"),
+    ("xtu", "っ"), ("ltu", "っ"), ("xtsu", "っ"), ("ltsu", "っ"),
+    ("xa", "ぁ"), ("xi", "ぃ"), ("xu", "ぅ"), ("xe", "ぇ"), ("xo", "ぉ"),
+    ("la", "ぁ"), ("li", "ぃ"), ("lu", "ぅ"), ("le", "ぇ"), ("lo", "ぉ"),
+    ("xya", "ゃ"), ("xyu", "ゅ"), ("xyo", "ょ"), ("lya", "ゃ"), ("lyu", "ゅ"), ("lyo", "ょ"),
+    ("xwa", "ゎ"), ("lwa", "ゎ"),
+    // 「ぢ」よりも外来語の「ディ」として入力されることが多い
+    ("di", "でぃ"),
+    ("thi", "てぃ"), ("thu", "てゅ"), ("dhi", "でぃ"), ("dhu", "でゅ"),
+    ("twu", "とぅ"), ("dwu", "どぅ"), ("whi", "うぃ"), ("whe", "うぇ"), ("who", "うぉ"),
+    ("tya", "ちゃ"), ("tyu", "ちゅ"), ("tyo", "ちょ"), ("dya", "ぢゃ"), ("dyu", "ぢゅ"), ("dyo", "ぢょ"),
+    ("cya", "ちゃ"), ("cyu", "ちゅ"), ("cyo", "ちょ"), ("cye", "ちぇ"),
+    ("jya", "じゃ"), ("jyu", "じゅ"), ("jyo", "じょ"), ("jye", "じぇ"),
+    ("zye", "じぇ"), ("sye", "しぇ"), ("tye", "ちぇ"),
+    ("ca", "か"), ("cu", "く"), ("co", "こ"), ("qa", "くぁ"),
+    ("fyu", "ふゅ"), ("vu", "ゔ"),
+];
+
+// 長音として畳み込む母音の並び（正規化したローマ字の比較用）
+const LONG_VOWEL_FOLDS: &[(&str, &str)] = &[
+    ("ou", "o"), ("oo", "o"), ("uu", "u"), ("aa", "a"), ("ii", "i"), ("ee", "e"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomajiScheme {
+    Hepburn,
+    Kunrei,
+    NihonShiki,
+}
+
+impl RomajiScheme {
+    pub const ALL: [RomajiScheme; 3] = [RomajiScheme::Hepburn, RomajiScheme::Kunrei, RomajiScheme::NihonShiki];
+
+    fn override_for(self, kana: &str) -> Option<&'static str> {
+        let find = |table: &[(&str, &'static str)]| {
+            table.iter().find(|(k, _)| *k == kana).map(|(_, romaji)| *romaji)
+        };
+        match self {
+            RomajiScheme::Hepburn => None,
+            RomajiScheme::Kunrei => find(KUNREI_OVERRIDES),
+            RomajiScheme::NihonShiki => find(NIHON_OVERRIDES).or_else(|| find(KUNREI_OVERRIDES)),
+        }
+    }
+}
+
+pub fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{3096}' | '\u{30A1}'..='\u{30FA}') || c == LONG_VOWEL_MARK
+}
+
+// カタカナをひらがなに揃える（ひらがなに対応のない文字はそのまま）
+pub fn katakana_to_hiragana(c: char) -> char {
+    match c {
+        '\u{30A1}'..='\u{30F6}' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+// かなをヘボン式のローマ字に変換する（かな以外の文字はそのまま残す）
+pub fn kana_to_romaji(text: &str) -> String {
+    kana_to_romaji_with(text, RomajiScheme::Hepburn)
+}
+
+pub fn kana_to_romaji_with(text: &str, scheme: RomajiScheme) -> String {
+    let chars: Vec<char> = text.chars().map(katakana_to_hiragana).collect();
+    let mut romaji = String::with_capacity(chars.len() * 2);
+    let mut pending_sokuon = false;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == SOKUON {
+            pending_sokuon = true;
+            i += 1;
+            continue;
+        }
+
+        if c == LONG_VOWEL_MARK {
+            // 直前の母音を伸ばす（ラーメン → raamen）
+            if let Some(vowel) = romaji.chars().last().filter(|v| is_vowel(*v)) {
+                romaji.push(vowel);
+            }
+            pending_sokuon = false;
+            i += 1;
+            continue;
+        }
+
+        let syllable = chars.get(i + 1)
+            .and_then(|&next| lookup_digraph(c, next).map(|syllable| (syllable, 2)))
+            .or_else(|| lookup_monograph(c).map(|syllable| (syllable, 1)))
+            .map(|(syllable, consumed)| {
+                let kana: String = chars[i..i + consumed].iter().collect();
+                (scheme.override_for(&kana).unwrap_or(syllable), consumed)
+            });
+
+        match syllable {
+            Some((syllable, consumed)) => {
+                if pending_sokuon {
+                    push_geminate(&mut romaji, syllable);
+                    pending_sokuon = false;
+                }
+                romaji.push_str(syllable);
+                i += consumed;
+            }
+            None => {
+                pending_sokuon = false;
+                romaji.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    romaji
+}
+
+// 入力されたローマ字（ヘボン式・訓令式・IME入力のいずれか）をかなに変換する
+// かなに変換できない文字はそのまま残す
+pub fn romaji_to_kana(text: &str) -> String {
+    let table = romaji_input_table();
+    let chars: Vec<char> = text.to_lowercase().chars().collect();
+    let mut kana = String::with_capacity(chars.len());
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        // 子音の重ね（kk, tt など）と tch は促音
+        let is_consonant = c.is_ascii_alphabetic() && !is_vowel(c) && c != 'n';
+        if is_consonant && (next == Some(c) || (c == 't' && next == Some('c'))) {
+            kana.push(SOKUON);
+            i += 1;
+            continue;
+        }
+
+        // nn は「ん」。ヘボン式の綴り（konnichiha）に合わせ、母音・y が続く場合は n を次の音に回す
+        if c == 'n' && next == Some('n') {
+            kana.push('ん');
+            let followed_by_vowel = chars.get(i + 2).is_some_and(|&c| is_vowel(c) || c == 'y');
+            i += if followed_by_vowel { 1 } else { 2 };
+            continue;
+        }
+
+        // 最長一致で変換する（母音・y が続かない n は「ん」）
+        let matched = (1..=4).rev().find_map(|len| {
+            if i + len > chars.len() {
+                return None;
+            }
+            let key: String = chars[i..i + len].iter().collect();
+            table.get(&key).map(|value| (value, len))
+        });
+
+        match matched {
+            Some((value, len)) => {
+                kana.push_str(value);
+                i += len;
+            }
+            None => {
+                kana.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    kana
+}
+
+// 方式の違いや長音の表記ゆれを吸収した比較用のローマ字
+// shiryou / siryou / siryo- はいずれも "shiryo" になる
+pub fn canonical_romaji(text: &str) -> String {
+    let kana = if text.chars().any(is_kana) {
+        text.to_string()
+    } else {
+        romaji_to_kana(text)
+    };
+
+    let mut romaji = kana_to_romaji(&kana);
+    for (from, to) in LONG_VOWEL_FOLDS {
+        while romaji.contains(from) {
+            romaji = romaji.replace(from, to);
+        }
+    }
+    romaji
+}
+
+// 文字列中のかなの連続部分を、各方式の綴りでローマ字に変換する
+pub fn kana_runs_to_romaji_variants(text: &str) -> Vec<String> {
+    let mut variants: Vec<String> = kana_runs(text)
+        .iter()
+        .flat_map(|run| RomajiScheme::ALL.iter().map(move |scheme| kana_to_romaji_with(run, *scheme)))
+        .filter(|romaji| !romaji.is_empty())
+        .collect();
+    variants.sort();
+    variants.dedup();
+    variants
+}
+
+// 文字列中のかなの連続部分を、比較用のローマ字に変換する
+pub fn kana_runs_to_canonical(text: &str) -> Vec<String> {
+    let mut canonical: Vec<String> = kana_runs(text)
+        .iter()
+        .map(|run| canonical_romaji(run))
+        .filter(|romaji| !romaji.is_empty())
+        .collect();
+    canonical.sort();
+    canonical.dedup();
+    canonical
+}
+
+fn kana_runs(text: &str) -> Vec<String> {
+    let mut runs = Vec::new();
+    let mut current_run = String::new();
+
+    for c in text.chars() {
+        if is_kana(c) {
+            current_run.push(c);
+        } else if !current_run.is_empty() {
+            runs.push(std::mem::take(&mut current_run));
+        }
+    }
+
+    if !current_run.is_empty() {
+        runs.push(current_run);
+    }
+    runs
+}
+
+fn romaji_input_table() -> &'static HashMap<String, String> {
+    static TABLE: OnceLock<HashMap<String, String>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = HashMap::new();
+        for (romaji, kana) in IME_INPUTS {
+            table.insert(romaji.to_string(), kana.to_string());
+        }
+
+        // 同じ綴りが複数のかなに対応する場合は、表の先にあるもの（一般的なもの）を優先する
+        let mut insert = |kana: String, scheme: RomajiScheme| {
+            let romaji = kana_to_romaji_with(&kana, scheme);
+            table.entry(romaji).or_insert(kana);
+        };
+        for scheme in RomajiScheme::ALL {
+            for (kana, _) in MONOGRAPHS.iter().chain(KATAKANA_ONLY.iter()) {
+                insert(kana.to_string(), scheme);
+            }
+        }
+        for scheme in RomajiScheme::ALL {
+            for (first, second, _) in DIGRAPHS {
+                insert(format!("{}{}", first, second), scheme);
+            }
+        }
+        table
+    })
+}
+
+fn lookup_digraph(first: char, second: char) -> Option<&'static str> {
+    DIGRAPHS.iter()
+        .find(|(a, b, _)| *a == first && *b == second)
+        .map(|(_, _, romaji)| *romaji)
+}
+
+fn lookup_monograph(c: char) -> Option<&'static str> {
+    MONOGRAPHS.iter()
+        .chain(KATAKANA_ONLY.iter())
+        .find(|(kana, _)| *kana == c)
+        .map(|(_, romaji)| *romaji)
+}
+
+// 促音（っ）は次の子音を重ねる。ヘボン式に従い ch の前は t にする
+fn push_geminate(romaji: &mut String, next_syllable: &str) {
+    if next_syllable.starts_with("ch") {
+        romaji.push('t');
+    } else if let Some(first) = next_syllable.chars().next().filter(|c| !is_vowel(*c) && *c != 'n') {
+        romaji.push(first);
+    }
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'i' | 'u' | 'e' | 'o')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_kana_to_romaji() {
+        let cases = [
+            // 濁音・半濁音のカタカナ
+            ("ガイド", "gaido"),
+            ("パワーポイント", "pawaapointo"),
+            ("データベース", "deetabeesu"),
+            ("ヴァイオリン", "vaiorin"),
+            // 拗音
+            ("キャンプ", "kyanpu"),
+            ("しりょう", "shiryou"),
+            ("ジャンプ", "janpu"),
+            ("ニュース", "nyuusu"),
+            ("ちょきん", "chokin"),
+            // 促音
+            ("きっぷ", "kippu"),
+            ("チェックリスト", "chekkurisuto"),
+            ("マッチ", "matchi"),
+            ("しゅっちょう", "shutchou"),
+            ("あっ", "a"),
+            // 長音
+            ("コーヒー", "koohii"),
+            ("ミーティング", "miitingu"),
+            ("スケジュール", "sukejuuru"),
+            // 外来語表記
+            ("ファイル", "fairu"),
+            ("ディレクトリ", "direkutori"),
+            ("シェア", "shea"),
+            ("ウィキ", "wiki"),
+            ("フォルダ", "foruda"),
+            // かな以外はそのまま
+            ("2025年度", "2025年度"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(kana_to_romaji(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn converts_kana_with_each_scheme() {
+        let cases = [
+            ("しりょう", "shiryou", "siryou", "siryou"),
+            ("ちず", "chizu", "tizu", "tizu"),
+            ("つづき", "tsuzuki", "tuzuki", "tuduki"),
+            ("ふじさん", "fujisan", "huzisan", "huzisan"),
+            ("しゅっちょう", "shutchou", "syuttyou", "syuttyou"),
+            ("はなぢ", "hanaji", "hanazi", "hanadi"),
+        ];
+
+        for (input, hepburn, kunrei, nihon) in cases {
+            assert_eq!(kana_to_romaji_with(input, RomajiScheme::Hepburn), hepburn, "input: {}", input);
+            assert_eq!(kana_to_romaji_with(input, RomajiScheme::Kunrei), kunrei, "input: {}", input);
+            assert_eq!(kana_to_romaji_with(input, RomajiScheme::NihonShiki), nihon, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn converts_typed_romaji_to_kana() {
+        let cases = [
+            ("shiryou", "しりょう"),
+            ("siryo-", "しりょー"),
+            ("kippu", "きっぷ"),
+            ("kixtupu", "きっぷ"),
+            ("matchi", "まっち"),
+            ("konnichiha", "こんにちは"),
+            ("shinnbunn", "しんぶん"),
+            ("kin'en", "きんえん"),
+            ("dhisuku", "でぃすく"),
+            ("tsukue", "つくえ"),
+            ("tukue", "つくえ"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(romaji_to_kana(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn normalizes_romaji_spellings_to_one_form() {
+        let cases = [
+            (&["しりょう", "shiryou", "siryou", "siryo-", "shiryo"][..], "shiryo"),
+            (&["ちゅうもん", "chuumon", "tyuumon", "cyu-mon", "chumon"][..], "chumon"),
+            (&["スケジュール", "sukejuuru", "sukezyu-ru", "sukejuru"][..], "sukejuru"),
+            (&["きっぷ", "kippu", "kixtupu"][..], "kippu"),
+            (&["ディスク", "disuku", "dhisuku"][..], "disuku"),
+        ];
+
+        for (inputs, expected) in cases {
+            for input in inputs {
+                assert_eq!(canonical_romaji(input), expected, "input: {}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn extracts_romaji_from_kana_runs() {
+        let cases: [(&str, &[&str]); 5] = [
+            ("議事録_営業会議", &[]),
+            ("営業資料_ドラフト", &["dorafuto"]),
+            ("キャンプ ガイド しりょう", &["gaido", "kyanpu", "shiryou"]),
+            ("2025年度_マーケティング計画", &["maaketingu"]),
+            ("プロジェクト提案書(ドラフト)", &["dorafuto", "purojekuto"]),
+        ];
+
+        for (input, expected) in cases {
+            let variants = kana_runs_to_romaji_variants(input);
+            assert_eq!(variants.is_empty(), expected.is_empty(), "input: {}", input);
+            for romaji in expected {
+                assert!(variants.contains(&romaji.to_string()), "input: {}, {:?}", input, variants);
+            }
+        }
+        // 訓令式などの綴りも含める
+        assert!(kana_runs_to_romaji_variants("しりょう").contains(&"siryou".to_string()));
+    }
+}